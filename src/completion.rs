@@ -0,0 +1,220 @@
+use crate::roots::FileAccess;
+use crate::validation::has_image_extension;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// Most values a `completion/complete` result may carry
+const MAX_COMPLETIONS: usize = 100;
+
+/// Arguments that name an input image
+const IMAGE_ARGUMENTS: &[&str] = &[
+    "image_source",
+    "source_image",
+    "style_image",
+    "primary_image",
+    "secondary_images",
+];
+
+pub fn is_image_argument(name: &str) -> bool {
+    IMAGE_ARGUMENTS.contains(&name)
+}
+
+/// Builds the `completion/complete` result, keeping at most `MAX_COMPLETIONS` values.
+pub fn completion_result(mut values: Vec<String>) -> Value {
+    values.sort();
+    values.dedup();
+    let total = values.len();
+    values.truncate(MAX_COMPLETIONS);
+    json!({
+        "completion": {
+            "values": values,
+            "total": total,
+            "hasMore": total > MAX_COMPLETIONS,
+        }
+    })
+}
+
+/// Completes `value` to existing image files, and to directories so the user
+/// can keep drilling down. Relative values are resolved against each of `bases`;
+/// only directories inside the roots of `files` are listed.
+pub async fn complete_image_path(
+    value: &str,
+    bases: &[PathBuf],
+    files: &FileAccess,
+) -> Vec<String> {
+    complete_path(value, bases, files, true).await
+}
+
+/// Completes `value` to directories, offering the known output directories
+/// that match what has been typed so far.
+pub async fn complete_output_path(
+    value: &str,
+    output_dirs: &[PathBuf],
+    bases: &[PathBuf],
+    files: &FileAccess,
+) -> Vec<String> {
+    let mut values: Vec<String> = output_dirs
+        .iter()
+        .map(|dir| with_trailing_slash(&dir.to_string_lossy()))
+        .filter(|dir| dir.starts_with(value))
+        .collect();
+    values.extend(complete_path(value, bases, files, false).await);
+    values
+}
+
+async fn complete_path(
+    value: &str,
+    bases: &[PathBuf],
+    files: &FileAccess,
+    include_images: bool,
+) -> Vec<String> {
+    let (dir, prefix) = match value.rfind('/') {
+        Some(index) => value.split_at(index + 1),
+        None => ("", value),
+    };
+
+    let dirs_to_read: Vec<PathBuf> = if Path::new(dir).is_absolute() {
+        vec![PathBuf::from(dir)]
+    } else {
+        bases.iter().map(|base| base.join(dir)).collect()
+    };
+
+    let mut values = vec![];
+    for dir_to_read in dirs_to_read {
+        // Listing a directory reveals its contents, so it is confined like a read
+        let Ok(dir_to_read) = files.check_read(&dir_to_read.to_string_lossy()).await else {
+            continue;
+        };
+        let Ok(mut entries) = fs::read_dir(&dir_to_read).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden entries only show up once the user types the dot
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            if file_type.is_dir() {
+                values.push(format!("{}{}/", dir, name));
+            } else if include_images && has_image_extension(&name) {
+                values.push(format!("{}{}", dir, name));
+            }
+        }
+    }
+    values
+}
+
+/// Offers the roots files may be read from and written to that match `value`.
+pub fn complete_roots(value: &str, roots: &[PathBuf]) -> Vec<String> {
+    roots
+        .iter()
+        .map(|root| with_trailing_slash(&root.to_string_lossy()))
+        .filter(|root| root.starts_with(value))
+        .collect()
+}
+
+fn with_trailing_slash(path: &str) -> String {
+    if path.ends_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/", path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("photos")).unwrap();
+        std::fs::create_dir(dir.path().join(".cache")).unwrap();
+        std::fs::write(dir.path().join("photos/cat.png"), [0]).unwrap();
+        std::fs::write(dir.path().join("photos/cat.txt"), [0]).unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), [0]).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_complete_image_path() {
+        let dir = tree();
+        let bases = vec![dir.path().to_path_buf()];
+        let files = FileAccess::new(&bases).await;
+
+        let mut values = complete_image_path("pho", &bases, &files).await;
+        values.sort();
+        assert_eq!(values, vec!["photo.jpg", "photos/"]);
+
+        assert_eq!(
+            complete_image_path("photos/c", &bases, &files).await,
+            vec!["photos/cat.png"]
+        );
+        assert!(
+            complete_image_path("", &bases, &files)
+                .await
+                .iter()
+                .all(|v| !v.starts_with('.'))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_output_path_offers_output_dirs() {
+        let dir = tree();
+        let bases = vec![dir.path().to_path_buf()];
+        let files = FileAccess::new(&bases).await;
+        let output_dirs = vec![PathBuf::from("renders"), PathBuf::from("exports/")];
+
+        let values = complete_output_path("r", &output_dirs, &bases, &files).await;
+        assert_eq!(values, vec!["renders/"]);
+
+        let values = complete_output_path("photos/", &output_dirs, &bases, &files).await;
+        assert!(values.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_directories_outside_the_roots_are_not_listed() {
+        let dir = tree();
+        let outside = tree();
+        let bases = vec![dir.path().to_path_buf()];
+        let files = FileAccess::new(&bases).await;
+        let outside_dir = format!("{}/", outside.path().display());
+
+        assert!(
+            complete_image_path(&outside_dir, &bases, &files)
+                .await
+                .is_empty()
+        );
+        assert!(
+            complete_output_path(&outside_dir, &[], &bases, &files)
+                .await
+                .is_empty()
+        );
+        assert!(complete_image_path("/", &bases, &files).await.is_empty());
+        // `..` can't climb out of a root either
+        assert!(complete_image_path("../", &bases, &files).await.is_empty());
+
+        let inside_dir = format!("{}/photos/", dir.path().display());
+        assert_eq!(
+            complete_image_path(&inside_dir, &bases, &files).await,
+            vec![format!("{}cat.png", inside_dir)]
+        );
+    }
+
+    #[test]
+    fn test_completion_result_is_capped() {
+        let values: Vec<String> = (0..150).map(|i| format!("{:03}.png", i)).collect();
+
+        let result = completion_result(values);
+
+        assert_eq!(
+            result["completion"]["values"].as_array().unwrap().len(),
+            100
+        );
+        assert_eq!(result["completion"]["total"], 150);
+        assert_eq!(result["completion"]["hasMore"], true);
+    }
+}
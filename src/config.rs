@@ -0,0 +1,207 @@
+use crate::error::{McpError, McpResult};
+use crate::gemini_client::{DEFAULT_ANALYSIS_MODEL, DEFAULT_IMAGE_MODEL, GenerationConfig};
+use crate::models::DEFAULT_MODELS_CACHE_TTL;
+use crate::roots::OverwritePolicy;
+use crate::tools::ToolSelection;
+use crate::vertex::VertexConfig;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Default number of Gemini API calls allowed to run at the same time
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+/// How often the `--config` file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Gemini API key; tools that call Gemini fail with a configuration error without it
+    pub api_key: Option<String>,
+    /// Gemini API endpoint, e.g. a proxy or a local stand-in; `None` uses the public API
+    pub api_base_url: Option<String>,
+    /// Call Gemini through Vertex AI instead of AI Studio; takes precedence over `api_key`
+    pub vertex: Option<VertexConfig>,
+    /// Model `analyze_image` uses when the call doesn't name one
+    pub analysis_model: String,
+    /// Model the generator tools use when the call doesn't name one
+    pub image_model: String,
+    /// How long `list_models` reuses a listing before asking the API again
+    pub models_cache_ttl: Duration,
+    /// Generation config fields used when a call doesn't set them
+    pub generation_defaults: GenerationConfig,
+    /// Upper bound on concurrently running Gemini API calls
+    pub max_concurrent_requests: usize,
+    /// Whether generator tools return the image as a content block when the call doesn't say
+    pub include_image: bool,
+    /// Images returned as content blocks are downscaled so neither side exceeds this many pixels
+    pub image_max_dimension: Option<u32>,
+    /// Directories whose images are listed as resources alongside this session's generations
+    pub output_dirs: Vec<PathBuf>,
+    /// Directory of TOML/Markdown prompt templates served next to the built-in prompts
+    pub prompts_dir: Option<PathBuf>,
+    /// Directories tools may read and write when the client doesn't declare roots;
+    /// empty means the working directory
+    pub allowed_dirs: Vec<PathBuf>,
+    /// What generator tools do with an existing output file when the call doesn't say
+    pub overwrite_policy: OverwritePolicy,
+    /// Tools offered to clients at startup
+    pub tools: ToolSelection,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            api_base_url: None,
+            vertex: None,
+            analysis_model: DEFAULT_ANALYSIS_MODEL.to_string(),
+            image_model: DEFAULT_IMAGE_MODEL.to_string(),
+            models_cache_ttl: DEFAULT_MODELS_CACHE_TTL,
+            generation_defaults: GenerationConfig::default(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            include_image: false,
+            image_max_dimension: None,
+            output_dirs: Vec::new(),
+            prompts_dir: None,
+            allowed_dirs: Vec::new(),
+            overwrite_policy: OverwritePolicy::default(),
+            tools: ToolSelection::default(),
+        }
+    }
+}
+
+/// Settings read from the `--config` TOML file, which is reloaded while the server runs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub tools: ToolsTable,
+}
+
+/// The `[tools]` table; keys left out keep their command-line value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolsTable {
+    pub enable_tools: Option<Vec<String>>,
+    pub disable_tools: Option<Vec<String>>,
+    pub read_only: Option<bool>,
+}
+
+impl ToolsTable {
+    pub fn apply(&self, base: &ToolSelection) -> ToolSelection {
+        ToolSelection {
+            enable_tools: self
+                .enable_tools
+                .clone()
+                .or_else(|| base.enable_tools.clone()),
+            disable_tools: self
+                .disable_tools
+                .clone()
+                .unwrap_or_else(|| base.disable_tools.clone()),
+            read_only: self.read_only.unwrap_or(base.read_only),
+        }
+    }
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> McpResult<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            McpError::ConfigurationError(format!("Cannot read {}: {}", path.display(), e))
+        })?;
+        toml::from_str(&contents).map_err(|e| {
+            McpError::ConfigurationError(format!("Invalid config file {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Loads the tool selection from `path` on top of `base`, then keeps reloading
+/// it when the file changes. A file that fails to load keeps the last good selection.
+pub fn watch_tool_selection(
+    path: PathBuf,
+    base: ToolSelection,
+) -> McpResult<watch::Receiver<ToolSelection>> {
+    let selection = ConfigFile::load(&path)?.tools.apply(&base);
+    let (tx, rx) = watch::channel(selection);
+
+    tokio::spawn(async move {
+        let mut last_modified = modified(&path);
+        let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if tx.is_closed() {
+                break;
+            }
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match ConfigFile::load(&path) {
+                Ok(config) => {
+                    let selection = config.tools.apply(&base);
+                    tx.send_if_modified(|active| {
+                        if *active == selection {
+                            return false;
+                        }
+                        info!("Reloaded tool selection from {}", path.display());
+                        *active = selection;
+                        true
+                    });
+                }
+                Err(e) => warn!("Keeping previous configuration: {}", e),
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tools_table_overrides_only_the_keys_it_sets() {
+        let base = ToolSelection {
+            enable_tools: None,
+            disable_tools: vec!["refine_image".to_string()],
+            read_only: false,
+        };
+        let config: ConfigFile = toml::from_str("[tools]\nread_only = true\n").unwrap();
+
+        let selection = config.tools.apply(&base);
+
+        assert!(selection.read_only);
+        assert_eq!(selection.disable_tools, vec!["refine_image"]);
+        assert!(toml::from_str::<ConfigFile>("[tools]\nreadonly = true\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_tool_selection_picks_up_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.toml");
+        std::fs::write(&path, "[tools]\nread_only = false\n").unwrap();
+        let mut rx = watch_tool_selection(path.clone(), ToolSelection::default()).unwrap();
+        assert!(!rx.borrow().read_only);
+
+        // Make sure the rewrite gets a different modification time
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::write(&path, "[tools]\nread_only = true\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(rx.borrow().read_only);
+    }
+}
@@ -6,7 +6,10 @@ pub enum McpError {
     IoError(std::io::Error),
     SerializationError(serde_json::Error),
     ConfigurationError(String),
-    GeminiApiError { code: i32, message: String },
+    GeminiApiError {
+        code: i32,
+        message: String,
+    },
     FileSystemError(String),
     Base64Error(base64::DecodeError),
     InvalidInput(String),
@@ -14,6 +17,9 @@ pub enum McpError {
     AuthenticationError(String),
     RateLimitError(String),
     ContentTypeError(String),
+    ClientRequestError(String),
+    /// The user declined or cancelled overwriting this output path
+    OverwriteDeclined(String),
 }
 
 impl fmt::Display for McpError {
@@ -33,6 +39,10 @@ impl fmt::Display for McpError {
             McpError::AuthenticationError(msg) => write!(f, "Authentication error: {}", msg),
             McpError::RateLimitError(msg) => write!(f, "Rate limit exceeded: {}", msg),
             McpError::ContentTypeError(msg) => write!(f, "Content type error: {}", msg),
+            McpError::ClientRequestError(msg) => write!(f, "Client request error: {}", msg),
+            McpError::OverwriteDeclined(path) => {
+                write!(f, "The user declined to overwrite '{}'", path)
+            }
         }
     }
 }
@@ -1,11 +1,34 @@
+use crate::config::DEFAULT_MAX_CONCURRENT_REQUESTS;
 use crate::error::{McpError, McpResult};
-use crate::image_service::ImageService;
-use crate::validation::{ImageSourceValidator, OutputPathValidator, PromptValidator, Validator};
+use crate::image_service::{ImageService, image_dimensions};
+use crate::models::{DEFAULT_MODELS_CACHE_TTL, ModelCatalog, ModelInfo, model_name};
+use crate::progress::{ProgressReporter, ProgressStage};
+use crate::roots::{FileAccess, OverwritePolicy};
+use crate::validation::{
+    ImageSourceValidator, OutputPathValidator, PromptValidator, Validator,
+    get_mime_type_from_extension,
+};
+use crate::vertex::{VertexAuth, VertexConfig};
 use base64::{Engine as _, engine::general_purpose};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::fs;
-use tracing::error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
+
+/// Gemini API endpoint used unless `--api-base-url` says otherwise
+pub const DEFAULT_API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+/// Model used by `analyze_image` unless `--analysis-model` or the call says otherwise
+pub const DEFAULT_ANALYSIS_MODEL: &str = "gemini-2.5-flash";
+/// Model used by the image generation and editing tools unless `--image-model` or the call says otherwise
+pub const DEFAULT_IMAGE_MODEL: &str = "gemini-2.5-flash-image-preview";
+/// Largest page the models endpoint is asked for
+const MODELS_PAGE_SIZE: u32 = 1000;
+
+/// How often a progress heartbeat is sent while waiting on the model
+const PROGRESS_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct AnalyzeImageInput {
@@ -15,6 +38,10 @@ pub struct AnalyzeImageInput {
     pub system_prompt: Option<String>,
     /// User prompt for analysis. Defaults to "Caption this image."
     pub user_prompt: Option<String>,
+    /// Gemini model to use (defaults to the server's analysis model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -25,6 +52,14 @@ pub struct GenerateImageInput {
     pub user_prompt: String,
     /// Output file path where the generated image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -37,6 +72,14 @@ pub struct EditImageInput {
     pub user_prompt: String,
     /// Output file path where the edited image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -51,6 +94,14 @@ pub struct InpaintImageInput {
     pub mask_description: Option<String>,
     /// Output file path where the inpainted image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -65,6 +116,14 @@ pub struct StyleTransferInput {
     pub user_prompt: Option<String>,
     /// Output file path where the style-transferred image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -79,6 +138,14 @@ pub struct ComposeImagesInput {
     pub user_prompt: String,
     /// Output file path where the composed image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -93,15 +160,212 @@ pub struct RefineImageInput {
     pub conversation_history: Option<Vec<String>>,
     /// Output file path where the refined image will be saved
     pub output_path: String,
+    /// Gemini model to use (defaults to the server's image model)
+    pub model: Option<String>,
+    /// Sampling settings such as temperature and seed (unset fields use the server defaults)
+    pub generation_config: Option<GenerationConfig>,
+    /// Return the image as an image content block in the tool result (defaults to the server setting)
+    pub include_image: Option<bool>,
+    /// What to do when output_path already exists (defaults to the server setting)
+    pub overwrite_policy: Option<OverwritePolicy>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Sampling settings sent to Gemini as `generationConfig`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GenerationConfig {
+    /// Randomness of the output, from 0.0 to 2.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Only tokens within this cumulative probability are sampled, from 0.0 to 1.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Only the K most likely tokens are sampled, at least 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Fixed seed, so repeated calls with the same input give the same output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+    /// Upper bound on tokens in the response, at least 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+}
+
+impl GenerationConfig {
+    /// Fills the fields this config leaves unset from `defaults`.
+    pub fn or(self, defaults: &GenerationConfig) -> Self {
+        Self {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            top_k: self.top_k.or(defaults.top_k),
+            seed: self.seed.or(defaults.seed),
+            max_output_tokens: self.max_output_tokens.or(defaults.max_output_tokens),
+        }
+    }
+
+    /// Checks the values against the ranges the Gemini API documents.
+    pub fn validate(&self) -> McpResult<()> {
+        if let Some(temperature) = self.temperature
+            && !(0.0..=2.0).contains(&temperature)
+        {
+            return Err(McpError::InvalidInput(format!(
+                "temperature must be between 0.0 and 2.0, got {}",
+                temperature
+            )));
+        }
+        if let Some(top_p) = self.top_p
+            && !(0.0..=1.0).contains(&top_p)
+        {
+            return Err(McpError::InvalidInput(format!(
+                "top_p must be between 0.0 and 1.0, got {}",
+                top_p
+            )));
+        }
+        if self.top_k == Some(0) {
+            return Err(McpError::InvalidInput(
+                "top_k must be at least 1".to_string(),
+            ));
+        }
+        if self.max_output_tokens == Some(0) {
+            return Err(McpError::InvalidInput(
+                "max_output_tokens must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Token counts Gemini reported for a call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TokenUsage {
+    /// Tokens in the request, including images
+    pub prompt_tokens: u64,
+    /// Tokens in the generated response
+    pub output_tokens: u64,
+    /// Total tokens billed for the call
+    pub total_tokens: u64,
+}
+
+/// Structured result of `analyze_image`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImageAnalysis {
+    /// The model's answer
+    pub text: String,
+    /// Gemini model that analyzed the image
+    pub model: String,
+    /// Sampling settings sent with the request, after server defaults
+    pub generation_config: Option<GenerationConfig>,
+    /// Token usage reported by Gemini, if any
+    pub usage: Option<TokenUsage>,
+}
+
+/// Structured result of the image generation and editing tools.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GeneratedImageOutput {
+    /// Path the image was saved to
+    pub file_path: String,
+    /// MIME type of the saved image
+    pub mime_type: String,
+    /// Size of the saved image in bytes
+    pub byte_size: u64,
+    /// Width in pixels, if the image format could be read
+    pub width: Option<u32>,
+    /// Height in pixels, if the image format could be read
+    pub height: Option<u32>,
+    /// Gemini model that produced the image
+    pub model: String,
+    /// Sampling settings sent with the request, after server defaults
+    pub generation_config: Option<GenerationConfig>,
+    /// Token usage reported by Gemini, if any
+    pub usage: Option<TokenUsage>,
+}
+
+/// An image produced by one of the generator tools and saved to disk.
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    pub file_path: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    pub model: String,
+    pub generation_config: Option<GenerationConfig>,
+    pub usage: Option<TokenUsage>,
+}
+
+impl GeneratedImage {
+    pub fn output(&self) -> GeneratedImageOutput {
+        let dimensions = image_dimensions(&self.data);
+        GeneratedImageOutput {
+            file_path: self.file_path.clone(),
+            mime_type: self.mime_type.clone(),
+            byte_size: self.data.len() as u64,
+            width: dimensions.map(|(width, _)| width),
+            height: dimensions.map(|(_, height)| height),
+            model: self.model.clone(),
+            generation_config: self.generation_config,
+            usage: self.usage.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 struct GeminiRequest {
     contents: Vec<GeminiContent>,
+    #[serde(
+        rename = "generationConfig",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_generation_config"
+    )]
+    generation_config: Option<GenerationConfig>,
+}
+
+/// `GenerationConfig` with the field names the Gemini API documents. The tool
+/// input keeps snake_case like every other argument.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WireGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+}
+
+fn serialize_generation_config<S: serde::Serializer>(
+    config: &Option<GenerationConfig>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    config
+        .map(|config| WireGenerationConfig {
+            temperature: config.temperature,
+            top_p: config.top_p,
+            top_k: config.top_k,
+            seed: config.seed,
+            max_output_tokens: config.max_output_tokens,
+        })
+        .serialize(serializer)
+}
+
+impl GeminiRequest {
+    fn new(parts: Vec<GeminiPart>, generation_config: Option<GenerationConfig>) -> Self {
+        Self {
+            contents: vec![GeminiContent {
+                role: Some("user".to_string()),
+                parts,
+            }],
+            generation_config,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct GeminiContent {
+    /// Vertex AI rejects request contents without a role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
     parts: Vec<GeminiPart>,
 }
 
@@ -122,6 +386,26 @@ struct InlineData {
 struct GeminiResponse {
     candidates: Option<Vec<GeminiCandidate>>,
     error: Option<GeminiError>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<GeminiUsageMetadata>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct GeminiUsageMetadata {
+    prompt_token_count: u64,
+    candidates_token_count: u64,
+    total_token_count: u64,
+}
+
+impl From<GeminiUsageMetadata> for TokenUsage {
+    fn from(usage: GeminiUsageMetadata) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_token_count,
+            output_tokens: usage.candidates_token_count,
+            total_tokens: usage.total_token_count,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -135,35 +419,29 @@ struct GeminiError {
     message: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct GeminiImageResponse {
-    candidates: Option<Vec<GeminiImageCandidate>>,
-    error: Option<GeminiError>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct GeminiImageCandidate {
-    content: GeminiImageContent,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct GeminiImageContent {
-    parts: Vec<GeminiImagePart>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-enum GeminiImagePart {
-    InlineData { inline_data: InlineData },
-}
-
 pub struct GeminiClient {
     client: reqwest::Client,
     image_service: ImageService,
     prompt_validator: PromptValidator,
     output_path_validator: OutputPathValidator,
     image_source_validator: ImageSourceValidator,
-    api_key: String,
+    backend: Backend,
+    base_url: String,
+    analysis_model: String,
+    image_model: String,
+    models: ModelCatalog,
+    models_cache_ttl: Duration,
+    generation_defaults: GenerationConfig,
+    /// Bounds the `generateContent` calls in flight; never closed
+    request_permits: Semaphore,
+}
+
+/// Which Gemini API the client talks to and how it authenticates.
+enum Backend {
+    /// Google AI Studio, authenticated with an API key
+    AiStudio { api_key: String },
+    /// Vertex AI in a project and region, authenticated as a service account
+    Vertex(Box<VertexAuth>),
 }
 
 impl GeminiClient {
@@ -174,8 +452,26 @@ impl GeminiClient {
             ));
         }
 
+        Self::with_backend(
+            Backend::AiStudio { api_key },
+            DEFAULT_API_BASE_URL.to_string(),
+        )
+    }
+
+    /// A client that calls Gemini through Vertex AI.
+    pub fn vertex(config: VertexConfig) -> McpResult<Self> {
+        let base_url = config.default_base_url();
+        Self::with_backend(Backend::Vertex(Box::new(VertexAuth::new(config))), base_url)
+    }
+
+    /// Whether calls go through Vertex AI rather than AI Studio.
+    pub fn is_vertex(&self) -> bool {
+        matches!(self.backend, Backend::Vertex(_))
+    }
+
+    fn with_backend(backend: Backend, base_url: String) -> McpResult<Self> {
         let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
+            .timeout(Duration::from_secs(60))
             .build()
             .map_err(McpError::NetworkError)?;
 
@@ -185,11 +481,142 @@ impl GeminiClient {
             prompt_validator: PromptValidator,
             output_path_validator: OutputPathValidator,
             image_source_validator: ImageSourceValidator,
-            api_key,
+            backend,
+            base_url,
+            analysis_model: DEFAULT_ANALYSIS_MODEL.to_string(),
+            image_model: DEFAULT_IMAGE_MODEL.to_string(),
+            models: ModelCatalog::new(),
+            models_cache_ttl: DEFAULT_MODELS_CACHE_TTL,
+            generation_defaults: GenerationConfig::default(),
+            request_permits: Semaphore::new(DEFAULT_MAX_CONCURRENT_REQUESTS),
         })
     }
 
-    pub async fn analyze_image(&self, input: &AnalyzeImageInput) -> McpResult<String> {
+    /// Sends every Gemini call to `base_url` instead of the public endpoint.
+    pub fn with_base_url(mut self, base_url: &str) -> McpResult<Self> {
+        self.base_url = parse_api_base_url(base_url)?;
+        Ok(self)
+    }
+
+    /// Sets the models used when a call doesn't name one. They are validated
+    /// like per-call models when first used.
+    pub fn with_default_models(mut self, analysis_model: &str, image_model: &str) -> Self {
+        self.analysis_model = model_name(analysis_model).to_string();
+        self.image_model = model_name(image_model).to_string();
+        self
+    }
+
+    /// Reuses `list_models` results for `ttl`.
+    pub fn with_models_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.models_cache_ttl = ttl;
+        self
+    }
+
+    /// Sets the generation config fields calls leave unset.
+    pub fn with_generation_defaults(mut self, defaults: GenerationConfig) -> Self {
+        self.generation_defaults = defaults;
+        self
+    }
+
+    /// Lets at most `limit` `generateContent` calls run at the same time; further
+    /// calls wait for a free slot right before they are sent.
+    pub fn with_max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.request_permits = Semaphore::new(limit.max(1));
+        self
+    }
+
+    /// Every model the API offers, cached for the models cache TTL. A fresh
+    /// listing also refreshes the catalog calls are validated against.
+    pub async fn list_models(&self) -> McpResult<Arc<Vec<ModelInfo>>> {
+        if let Some(models) = self.models.cached_listing(self.models_cache_ttl) {
+            return Ok(models);
+        }
+        let models = self.fetch_models().await?;
+        info!("Listed {} models", models.len());
+        Ok(self.models.replace(models))
+    }
+
+    /// Pages through the models endpoint, following `nextPageToken`.
+    async fn fetch_models(&self) -> McpResult<Vec<ModelInfo>> {
+        if matches!(self.backend, Backend::Vertex(_)) {
+            return Err(McpError::ConfigurationError(
+                "Listing models is only supported with the AI Studio API".to_string(),
+            ));
+        }
+
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(format!("{}/models", self.base_url))
+                .query(&[("pageSize", MODELS_PAGE_SIZE.to_string())]);
+            if let Some(token) = &page_token {
+                request = request.query(&[("pageToken", token)]);
+            }
+            let response = self
+                .check_status(self.authorize(request).await?.send().await?)
+                .await?;
+            let page: ModelsPage = response.json().await?;
+            models.extend(page.models);
+            match page.next_page_token.filter(|token| !token.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+
+    /// The call's generation config over the server defaults, or `None` when neither sets anything.
+    fn generation_config(
+        &self,
+        requested: Option<&GenerationConfig>,
+    ) -> McpResult<Option<GenerationConfig>> {
+        let config = requested
+            .copied()
+            .unwrap_or_default()
+            .or(&self.generation_defaults);
+        config.validate()?;
+        Ok((config != GenerationConfig::default()).then_some(config))
+    }
+
+    /// The model a call runs on: `requested`, or `default` when the call names none.
+    /// A model missing from the catalog triggers a refresh from the models endpoint
+    /// before it is rejected. Vertex AI has no such listing, so there the API
+    /// rejects unknown models itself.
+    async fn resolve_model(&self, requested: Option<&str>, default: &str) -> McpResult<String> {
+        let model = requested.map(model_name).unwrap_or(default);
+        if matches!(self.backend, Backend::Vertex(_)) || self.models.contains(model) {
+            return Ok(model.to_string());
+        }
+
+        if self.models.begin_refresh() {
+            match self.fetch_models().await {
+                Ok(listed) => {
+                    info!("Refreshed model catalog: {} models", listed.len());
+                    self.models.replace(listed);
+                }
+                Err(e) => warn!("Could not refresh the model catalog: {}", e),
+            }
+            if self.models.contains(model) {
+                return Ok(model.to_string());
+            }
+        }
+
+        Err(McpError::InvalidInput(format!(
+            "Unknown model '{}'. Known models: {}",
+            model,
+            self.models.names().join(", ")
+        )))
+    }
+
+    pub async fn analyze_image(
+        &self,
+        input: &AnalyzeImageInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<ImageAnalysis> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         let user_prompt = input
             .user_prompt
             .as_deref()
@@ -199,10 +626,15 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.analysis_model)
+            .await?;
 
+        progress.fetching_image(0, 1, &input.image_source);
         let (mime_type, encoded_image) = self
             .image_service
-            .fetch_and_encode(&input.image_source)
+            .fetch_and_encode(&input.image_source, files)
             .await
             .map_err(|e| {
                 error!(
@@ -234,46 +666,14 @@ impl GeminiClient {
             text: user_prompt.to_string(),
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        let url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent";
+        let request = GeminiRequest::new(parts, generation_config);
 
         let response = self
-            .client
-            .post(url)
-            .header("x-goog-api-key", &self.api_key)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Failed to send request to Gemini API: {}", e);
-                e
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            error!(
-                "Gemini API returned error status {}: {}",
-                status, error_text
-            );
+            .send_generate_content(&model, &request, progress)
+            .await?;
 
-            return match status.as_u16() {
-                401 => Err(McpError::AuthenticationError("Invalid API key".to_string())),
-                429 => Err(McpError::RateLimitError(
-                    "Gemini API rate limit exceeded".to_string(),
-                )),
-                _ => Err(McpError::GeminiApiError {
-                    code: status.as_u16() as i32,
-                    message: error_text,
-                }),
-            };
-        }
-
-        let gemini_response: GeminiResponse = response.json().await.map_err(|e| {
+        progress.stage(ProgressStage::Decoding, "Decoding model response");
+        let mut gemini_response: GeminiResponse = response.json().await.map_err(|e| {
             error!("Failed to parse Gemini API response: {}", e);
             McpError::NetworkError(e)
         })?;
@@ -289,16 +689,34 @@ impl GeminiClient {
             });
         }
 
-        self.extract_text_from_response(gemini_response)
+        let usage = gemini_response.usage_metadata.take().map(TokenUsage::from);
+        let text = self.extract_text_from_response(gemini_response)?;
+        progress.finished("Analysis complete");
+        Ok(ImageAnalysis {
+            text,
+            model,
+            generation_config: request.generation_config,
+            usage,
+        })
     }
 
-    pub async fn generate_image(&self, input: &GenerateImageInput) -> McpResult<String> {
+    pub async fn generate_image(
+        &self,
+        input: &GenerateImageInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.prompt_validator.validate(&input.user_prompt)?;
         self.output_path_validator.validate(&input.output_path)?;
 
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
         let mut parts = vec![];
 
@@ -312,94 +730,26 @@ impl GeminiClient {
             text: input.user_prompt.clone(),
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        let url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image-preview:generateContent";
-
-        let response = self
-            .client
-            .post(url)
-            .header("x-goog-api-key", &self.api_key)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                error!(
-                    "Failed to send request to Gemini API for image generation: {}",
-                    e
-                );
-                e
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            error!(
-                "Gemini API returned error status {}: {}",
-                status, error_text
-            );
-
-            return match status.as_u16() {
-                401 => Err(McpError::AuthenticationError("Invalid API key".to_string())),
-                429 => Err(McpError::RateLimitError(
-                    "Gemini API rate limit exceeded".to_string(),
-                )),
-                _ => Err(McpError::GeminiApiError {
-                    code: status.as_u16() as i32,
-                    message: error_text,
-                }),
-            };
-        }
-
-        // Parse as generic JSON first to inspect the structure
-        let response_text = response.text().await.map_err(|e| {
-            error!("Failed to get response text for image generation: {}", e);
-            McpError::NetworkError(e)
-        })?;
-
-        let json_value: serde_json::Value = serde_json::from_str(&response_text).map_err(|e| {
-            error!("Failed to parse response as JSON: {}", e);
-            error!("Response text was: {}", response_text);
-            McpError::InvalidInput(format!("Failed to parse API response as JSON: {}", e))
-        })?;
-
-        // Check for API errors in the response
-        if let Some(error) = json_value.get("error") {
-            let code = error.get("code").and_then(|c| c.as_i64()).unwrap_or(0) as i32;
-            let message = error
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error")
-                .to_string();
-            error!("Gemini API returned error: {} - {}", code, message);
-            return Err(McpError::GeminiApiError { code, message });
-        }
-
-        let base64_image_data = self.extract_image_from_json(json_value)?;
-
-        // Decode the base64 image data
-        let image_bytes = general_purpose::STANDARD
-            .decode(&base64_image_data)
-            .map_err(|e| {
-                error!("Failed to decode base64 image data: {}", e);
-                McpError::Base64Error(e)
-            })?;
-
-        // Save the image to the specified path
-        fs::write(&input.output_path, &image_bytes)
-            .await
-            .map_err(|e| {
-                error!("Failed to write image to '{}': {}", input.output_path, e);
-                McpError::FileSystemError(format!("Failed to write image file: {}", e))
-            })?;
-
-        Ok(input.output_path.clone())
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
-    pub async fn edit_image(&self, input: &EditImageInput) -> McpResult<String> {
+    pub async fn edit_image(
+        &self,
+        input: &EditImageInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.image_source_validator.validate(&input.image_source)?;
         self.prompt_validator.validate(&input.user_prompt)?;
         self.output_path_validator.validate(&input.output_path)?;
@@ -407,10 +757,15 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
+        progress.fetching_image(0, 1, &input.image_source);
         let (mime_type, encoded_image) = self
             .image_service
-            .fetch_and_encode(&input.image_source)
+            .fetch_and_encode(&input.image_source, files)
             .await
             .map_err(|e| {
                 error!(
@@ -442,97 +797,26 @@ impl GeminiClient {
             },
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        let url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image-preview:generateContent";
-
-        let response = self
-            .client
-            .post(url)
-            .header("x-goog-api-key", &self.api_key)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                error!(
-                    "Failed to send request to Gemini API for image editing: {}",
-                    e
-                );
-                e
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            error!(
-                "Gemini API returned error status {}: {}",
-                status, error_text
-            );
-
-            return match status.as_u16() {
-                401 => Err(McpError::AuthenticationError("Invalid API key".to_string())),
-                429 => Err(McpError::RateLimitError(
-                    "Gemini API rate limit exceeded".to_string(),
-                )),
-                _ => Err(McpError::GeminiApiError {
-                    code: status.as_u16() as i32,
-                    message: error_text,
-                }),
-            };
-        }
-
-        // Parse as generic JSON first to inspect the structure
-        let response_text = response.text().await.map_err(|e| {
-            error!("Failed to get response text for image editing: {}", e);
-            McpError::NetworkError(e)
-        })?;
-
-        let json_value: serde_json::Value = serde_json::from_str(&response_text).map_err(|e| {
-            error!("Failed to parse response as JSON: {}", e);
-            error!("Response text was: {}", response_text);
-            McpError::InvalidInput(format!("Failed to parse API response as JSON: {}", e))
-        })?;
-
-        // Check for API errors in the response
-        if let Some(error) = json_value.get("error") {
-            let code = error.get("code").and_then(|c| c.as_i64()).unwrap_or(0) as i32;
-            let message = error
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error")
-                .to_string();
-            error!("Gemini API returned error: {} - {}", code, message);
-            return Err(McpError::GeminiApiError { code, message });
-        }
-
-        let base64_image_data = self.extract_image_from_json(json_value)?;
-
-        // Decode the base64 image data
-        let image_bytes = general_purpose::STANDARD
-            .decode(&base64_image_data)
-            .map_err(|e| {
-                error!("Failed to decode base64 image data: {}", e);
-                McpError::Base64Error(e)
-            })?;
-
-        // Save the edited image to the specified path
-        fs::write(&input.output_path, &image_bytes)
-            .await
-            .map_err(|e| {
-                error!(
-                    "Failed to write edited image to '{}': {}",
-                    input.output_path, e
-                );
-                McpError::FileSystemError(format!("Failed to write edited image file: {}", e))
-            })?;
-
-        Ok(input.output_path.clone())
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
-    pub async fn inpaint_image(&self, input: &InpaintImageInput) -> McpResult<String> {
+    pub async fn inpaint_image(
+        &self,
+        input: &InpaintImageInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.image_source_validator.validate(&input.image_source)?;
         self.prompt_validator.validate(&input.user_prompt)?;
         self.output_path_validator.validate(&input.output_path)?;
@@ -540,10 +824,15 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
+        progress.fetching_image(0, 1, &input.image_source);
         let (mime_type, encoded_image) = self
             .image_service
-            .fetch_and_encode(&input.image_source)
+            .fetch_and_encode(&input.image_source, files)
             .await
             .map_err(|e| {
                 error!(
@@ -582,15 +871,26 @@ impl GeminiClient {
             },
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        self.generate_image_from_request(request, &input.output_path)
-            .await
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
-    pub async fn style_transfer(&self, input: &StyleTransferInput) -> McpResult<String> {
+    pub async fn style_transfer(
+        &self,
+        input: &StyleTransferInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.image_source_validator.validate(&input.source_image)?;
         self.image_source_validator.validate(&input.style_image)?;
         self.output_path_validator.validate(&input.output_path)?;
@@ -598,10 +898,15 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
+        progress.fetching_image(0, 2, &input.source_image);
         let (source_mime, source_encoded) = self
             .image_service
-            .fetch_and_encode(&input.source_image)
+            .fetch_and_encode(&input.source_image, files)
             .await
             .map_err(|e| {
                 error!(
@@ -611,9 +916,10 @@ impl GeminiClient {
                 e
             })?;
 
+        progress.fetching_image(1, 2, &input.style_image);
         let (style_mime, style_encoded) = self
             .image_service
-            .fetch_and_encode(&input.style_image)
+            .fetch_and_encode(&input.style_image, files)
             .await
             .map_err(|e| {
                 error!(
@@ -657,15 +963,26 @@ impl GeminiClient {
             text: prompt.to_string(),
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        self.generate_image_from_request(request, &input.output_path)
-            .await
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
-    pub async fn compose_images(&self, input: &ComposeImagesInput) -> McpResult<String> {
+    pub async fn compose_images(
+        &self,
+        input: &ComposeImagesInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.image_source_validator.validate(&input.primary_image)?;
         for secondary_image in &input.secondary_images {
             self.image_source_validator.validate(secondary_image)?;
@@ -676,6 +993,10 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
         let mut parts = vec![];
 
@@ -686,10 +1007,13 @@ impl GeminiClient {
             });
         }
 
+        let image_count = 1 + input.secondary_images.len();
+
         // Add primary image
+        progress.fetching_image(0, image_count, &input.primary_image);
         let (primary_mime, primary_encoded) = self
             .image_service
-            .fetch_and_encode(&input.primary_image)
+            .fetch_and_encode(&input.primary_image, files)
             .await
             .map_err(|e| {
                 error!(
@@ -707,10 +1031,11 @@ impl GeminiClient {
         });
 
         // Add secondary images
-        for secondary_image in &input.secondary_images {
+        for (i, secondary_image) in input.secondary_images.iter().enumerate() {
+            progress.fetching_image(i + 1, image_count, secondary_image);
             let (secondary_mime, secondary_encoded) = self
                 .image_service
-                .fetch_and_encode(secondary_image)
+                .fetch_and_encode(secondary_image, files)
                 .await
                 .map_err(|e| {
                     error!(
@@ -733,15 +1058,26 @@ impl GeminiClient {
             text: input.user_prompt.clone(),
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        self.generate_image_from_request(request, &input.output_path)
-            .await
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
-    pub async fn refine_image(&self, input: &RefineImageInput) -> McpResult<String> {
+    pub async fn refine_image(
+        &self,
+        input: &RefineImageInput,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        progress.stage(ProgressStage::Validating, "Validating input");
         self.image_source_validator.validate(&input.image_source)?;
         self.prompt_validator.validate(&input.user_prompt)?;
         self.output_path_validator.validate(&input.output_path)?;
@@ -749,10 +1085,15 @@ impl GeminiClient {
         if let Some(ref system_prompt) = input.system_prompt {
             self.prompt_validator.validate(system_prompt)?;
         }
+        let generation_config = self.generation_config(input.generation_config.as_ref())?;
+        let model = self
+            .resolve_model(input.model.as_deref(), &self.image_model)
+            .await?;
 
+        progress.fetching_image(0, 1, &input.image_source);
         let (mime_type, encoded_image) = self
             .image_service
-            .fetch_and_encode(&input.image_source)
+            .fetch_and_encode(&input.image_source, files)
             .await
             .map_err(|e| {
                 error!(
@@ -793,54 +1134,46 @@ impl GeminiClient {
             },
         });
 
-        let request = GeminiRequest {
-            contents: vec![GeminiContent { parts }],
-        };
-
-        self.generate_image_from_request(request, &input.output_path)
-            .await
+        let request = GeminiRequest::new(parts, generation_config);
+
+        self.generate_image_from_request(
+            request,
+            &input.output_path,
+            input.overwrite_policy,
+            model,
+            files,
+            progress,
+        )
+        .await
     }
 
     async fn generate_image_from_request(
         &self,
         request: GeminiRequest,
         output_path: &str,
-    ) -> McpResult<String> {
-        let url = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image-preview:generateContent";
-
+        overwrite_policy: Option<OverwritePolicy>,
+        model: String,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        // Resolved before calling the model so a rejected path or a declined
+        // overwrite costs no generation
+        let output = files.prepare_output(output_path, overwrite_policy).await?;
+        let resolved_path = output.path();
+        // Report a renamed file the way the caller spelled the original path
+        let output_path = match resolved_path.file_name() {
+            Some(name) => Path::new(output_path)
+                .with_file_name(name)
+                .to_string_lossy()
+                .into_owned(),
+            None => output_path.to_string(),
+        };
+        let output_path = output_path.as_str();
         let response = self
-            .client
-            .post(url)
-            .header("x-goog-api-key", &self.api_key)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Failed to send request to Gemini API: {}", e);
-                e
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            error!(
-                "Gemini API returned error status {}: {}",
-                status, error_text
-            );
-
-            return match status.as_u16() {
-                401 => Err(McpError::AuthenticationError("Invalid API key".to_string())),
-                429 => Err(McpError::RateLimitError(
-                    "Gemini API rate limit exceeded".to_string(),
-                )),
-                _ => Err(McpError::GeminiApiError {
-                    code: status.as_u16() as i32,
-                    message: error_text,
-                }),
-            };
-        }
+            .send_generate_content(&model, &request, progress)
+            .await?;
 
+        progress.stage(ProgressStage::Decoding, "Decoding generated image");
         let response_text = response.text().await.map_err(|e| {
             error!("Failed to get response text: {}", e);
             McpError::NetworkError(e)
@@ -864,7 +1197,11 @@ impl GeminiClient {
             return Err(McpError::GeminiApiError { code, message });
         }
 
-        let base64_image_data = self.extract_image_from_json(json_value)?;
+        let usage = json_value
+            .get("usageMetadata")
+            .and_then(|usage| serde_json::from_value::<GeminiUsageMetadata>(usage.clone()).ok())
+            .map(TokenUsage::from);
+        let (mime_type, base64_image_data) = self.extract_image_from_json(json_value)?;
 
         // Decode the base64 image data
         let image_bytes = general_purpose::STANDARD
@@ -875,12 +1212,132 @@ impl GeminiClient {
             })?;
 
         // Save the image to the specified path
-        fs::write(output_path, &image_bytes).await.map_err(|e| {
-            error!("Failed to write image to '{}': {}", output_path, e);
-            McpError::FileSystemError(format!("Failed to write image file: {}", e))
+        progress.stage(
+            ProgressStage::WritingOutput,
+            format!("Writing image to {}", output_path),
+        );
+        self.image_service
+            .save_image(&resolved_path.to_string_lossy(), image_bytes.clone())
+            .await
+            .map_err(|e| {
+                error!("Failed to write image to '{}': {}", output_path, e);
+                McpError::FileSystemError(format!("Failed to write image file: {}", e))
+            })?;
+        progress.finished(format!("Image written to {}", output_path));
+
+        Ok(GeneratedImage {
+            file_path: output_path.to_string(),
+            mime_type: mime_type.unwrap_or_else(|| get_mime_type_from_extension(output_path)),
+            data: image_bytes,
+            model,
+            generation_config: request.generation_config,
+            usage,
+        })
+    }
+
+    /// Posts a `generateContent` request to `model` and maps error statuses to `McpError`.
+    /// While the model works, a progress heartbeat is emitted every few seconds.
+    /// Only the send itself holds one of the concurrency permits.
+    async fn send_generate_content(
+        &self,
+        model: &str,
+        request: &GeminiRequest,
+        progress: &ProgressReporter,
+    ) -> McpResult<reqwest::Response> {
+        let url = match &self.backend {
+            Backend::AiStudio { .. } => {
+                format!("{}/models/{}:generateContent", self.base_url, model)
+            }
+            Backend::Vertex(auth) => auth.model_url(&self.base_url, model),
+        };
+        let post = self.authorize(self.client.post(url)).await?;
+        let body = serde_json::to_vec(request)?;
+
+        if self.request_permits.available_permits() == 0 {
+            debug!("Waiting for a free Gemini request slot");
+        }
+        let _permit = self
+            .request_permits
+            .acquire()
+            .await
+            .expect("Gemini request semaphore closed");
+        // Reported only once the request holds a slot and is actually sent
+        progress.stage(
+            ProgressStage::Uploading,
+            format!("Uploading request to Gemini ({} KB)", body.len() / 1024),
+        );
+        let send = post
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send();
+        tokio::pin!(send);
+
+        progress.stage(ProgressStage::WaitingForModel, "Waiting for the model");
+        let started = tokio::time::Instant::now();
+        let mut heartbeat = tokio::time::interval_at(
+            started + PROGRESS_HEARTBEAT_INTERVAL,
+            PROGRESS_HEARTBEAT_INTERVAL,
+        );
+        let mut ticks = 0;
+        let response = loop {
+            tokio::select! {
+                response = &mut send => break response,
+                _ = heartbeat.tick() => {
+                    ticks += 1;
+                    progress.waiting_for_model(ticks, started.elapsed().as_secs());
+                }
+            }
+        }
+        .map_err(|e| {
+            error!("Failed to send request to Gemini API: {}", e);
+            e
         })?;
 
-        Ok(output_path.to_string())
+        self.check_status(response).await
+    }
+
+    /// Adds the backend's credentials to `request`.
+    async fn authorize(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> McpResult<reqwest::RequestBuilder> {
+        Ok(match &self.backend {
+            Backend::AiStudio { api_key } => request.header("x-goog-api-key", api_key),
+            Backend::Vertex(auth) => request.bearer_auth(auth.access_token(&self.client).await?),
+        })
+    }
+
+    /// Maps error statuses from the Gemini API to `McpError`.
+    async fn check_status(&self, response: reqwest::Response) -> McpResult<reqwest::Response> {
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!(
+                "Gemini API returned error status {}: {}",
+                status, error_text
+            );
+
+            return match (status.as_u16(), &self.backend) {
+                (401, Backend::AiStudio { .. }) => {
+                    Err(McpError::AuthenticationError("Invalid API key".to_string()))
+                }
+                (401, Backend::Vertex(auth)) => {
+                    auth.invalidate().await;
+                    Err(McpError::AuthenticationError(
+                        "Vertex AI rejected the access token".to_string(),
+                    ))
+                }
+                (429, _) => Err(McpError::RateLimitError(
+                    "Gemini API rate limit exceeded".to_string(),
+                )),
+                _ => Err(McpError::GeminiApiError {
+                    code: status.as_u16() as i32,
+                    message: error_text,
+                }),
+            };
+        }
+
+        Ok(response)
     }
 
     fn extract_text_from_response(&self, response: GeminiResponse) -> McpResult<String> {
@@ -920,7 +1377,11 @@ impl GeminiClient {
         }
     }
 
-    fn extract_image_from_json(&self, json_value: serde_json::Value) -> McpResult<String> {
+    /// Returns the MIME type reported by the API, if any, and the base64 image data.
+    fn extract_image_from_json(
+        &self,
+        json_value: serde_json::Value,
+    ) -> McpResult<(Option<String>, String)> {
         // Try to extract image data from JSON response
         // The response structure could be: candidates[0].content.parts[0].inline_data.data
         if let Some(candidates) = json_value.get("candidates").and_then(|c| c.as_array()) {
@@ -948,7 +1409,12 @@ impl GeminiClient {
                         part.get("inline_data").or_else(|| part.get("inlineData"))
                         && let Some(data) = inline_data.get("data").and_then(|d| d.as_str())
                     {
-                        return Ok(data.to_string());
+                        let mime_type = inline_data
+                            .get("mime_type")
+                            .or_else(|| inline_data.get("mimeType"))
+                            .and_then(|m| m.as_str())
+                            .map(str::to_string);
+                        return Ok((mime_type, data.to_string()));
                     }
                 }
             }
@@ -961,10 +1427,51 @@ impl GeminiClient {
     }
 }
 
+/// One page of the `models` endpoint.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelsPage {
+    #[serde(default)]
+    models: Vec<ModelInfo>,
+    next_page_token: Option<String>,
+}
+
+/// Checks an API base URL and strips its trailing slash.
+pub fn parse_api_base_url(base_url: &str) -> McpResult<String> {
+    parse_endpoint_url("API base URL", base_url)
+}
+
+/// Checks the URL of an endpoint the server sends credentials to. Plain HTTP is
+/// only accepted for loopback hosts, so credentials never cross the network in clear text.
+pub(crate) fn parse_endpoint_url(what: &str, endpoint: &str) -> McpResult<String> {
+    let url = reqwest::Url::parse(endpoint.trim()).map_err(|e| {
+        McpError::ConfigurationError(format!("Invalid {} '{}': {}", what, endpoint, e))
+    })?;
+    let is_loopback = url
+        .host_str()
+        .is_some_and(|host| matches!(host, "localhost" | "127.0.0.1" | "[::1]"));
+    match url.scheme() {
+        "https" => {}
+        "http" if is_loopback => {}
+        "http" => {
+            return Err(McpError::ConfigurationError(format!(
+                "{} '{}' must use https unless it points at localhost",
+                what, endpoint
+            )));
+        }
+        scheme => {
+            return Err(McpError::ConfigurationError(format!(
+                "{} '{}' has unsupported scheme '{}'",
+                what, endpoint, scheme
+            )));
+        }
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json;
 
     #[test]
     fn test_inpaint_image_input_serialization() {
@@ -974,6 +1481,10 @@ mod tests {
             user_prompt: "Replace with a dog".to_string(),
             mask_description: Some("the cat".to_string()),
             output_path: "./test/output.png".to_string(),
+            model: None,
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
         };
 
         let json = serde_json::to_string(&input).unwrap();
@@ -998,6 +1509,10 @@ mod tests {
             system_prompt: None,
             user_prompt: Some("Transfer artistic style".to_string()),
             output_path: "./test/output.png".to_string(),
+            model: None,
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
         };
 
         let json = serde_json::to_string(&input).unwrap();
@@ -1026,6 +1541,10 @@ mod tests {
             system_prompt: Some("Compose creatively".to_string()),
             user_prompt: "Create a collage".to_string(),
             output_path: "./test/composed.png".to_string(),
+            model: None,
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
         };
 
         let json = serde_json::to_string(&input).unwrap();
@@ -1052,6 +1571,10 @@ mod tests {
                 "Second iteration: improved lighting".to_string(),
             ]),
             output_path: "./test/refined.png".to_string(),
+            model: None,
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
         };
 
         let json = serde_json::to_string(&input).unwrap();
@@ -1155,10 +1678,450 @@ mod tests {
             user_prompt: "Test".to_string(),
             mask_description: None,
             output_path: "./test/output.png".to_string(),
+            model: None,
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
         };
 
         // Should serialize properly even with long paths (validation happens elsewhere)
         let json = serde_json::to_string(&input).unwrap();
         assert!(json.len() > 3000);
     }
+
+    #[test]
+    fn test_usage_metadata_maps_to_token_usage() {
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [],
+            "usageMetadata": {
+                "promptTokenCount": 1290,
+                "candidatesTokenCount": 12,
+                "totalTokenCount": 1302
+            }
+        }))
+        .unwrap();
+
+        let usage = TokenUsage::from(response.usage_metadata.unwrap());
+        assert_eq!(
+            usage,
+            TokenUsage {
+                prompt_tokens: 1290,
+                output_tokens: 12,
+                total_tokens: 1302,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_api_base_url() {
+        assert_eq!(
+            parse_api_base_url("https://proxy.example.com/gemini/v1beta/").unwrap(),
+            "https://proxy.example.com/gemini/v1beta"
+        );
+        assert_eq!(
+            parse_api_base_url("http://127.0.0.1:9000").unwrap(),
+            "http://127.0.0.1:9000"
+        );
+        assert!(parse_api_base_url("http://localhost:9000/v1beta").is_ok());
+        assert!(parse_api_base_url("http://[::1]:9000").is_ok());
+        assert!(parse_api_base_url("http://proxy.example.com").is_err());
+        assert!(parse_api_base_url("ftp://localhost").is_err());
+        assert!(parse_api_base_url("not a url").is_err());
+    }
+
+    /// A stand-in for the Gemini API that answers every call with the
+    /// credential it received and the path it was sent to. Like Vertex AI, it
+    /// rejects contents without a role.
+    async fn echo_server() -> std::net::SocketAddr {
+        let router = axum::Router::new()
+            .route(
+                "/token",
+                axum::routing::post(|| async {
+                    axum::Json(serde_json::json!({ "access_token": "vertex-token", "expires_in": 3600 }))
+                }),
+            )
+            .route(
+                "/v1beta/models",
+                axum::routing::get(
+                    |axum::extract::Query(query): axum::extract::Query<
+                        std::collections::HashMap<String, String>,
+                    >| async move {
+                        let methods = ["generateContent"];
+                        axum::Json(match query.get("pageToken").map(String::as_str) {
+                            None => serde_json::json!({
+                                "models": [{ "name": "models/gemini-2.5-flash", "supportedGenerationMethods": methods }],
+                                "nextPageToken": "page-2"
+                            }),
+                            _ => serde_json::json!({
+                                "models": [{ "name": "models/gemini-3-pro-preview", "supportedGenerationMethods": methods }]
+                            }),
+                        })
+                    },
+                ),
+            )
+            .fallback(|request: axum::extract::Request| async move {
+                let headers = request.headers();
+                let credential = headers
+                    .get("x-goog-api-key")
+                    .or(headers.get("authorization"))
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                let uri = request.uri().clone();
+                let body = axum::body::to_bytes(request.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let roles_valid = body["contents"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .all(|content| matches!(content["role"].as_str(), Some("user" | "model")));
+                if !roles_valid {
+                    return (
+                        axum::http::StatusCode::BAD_REQUEST,
+                        axum::Json(serde_json::json!({
+                            "error": { "code": 400, "message": "Please use a valid role: user, model." }
+                        })),
+                    );
+                }
+                (
+                    axum::http::StatusCode::OK,
+                    axum::Json(serde_json::json!({
+                        "candidates": [{
+                            "content": {
+                                "role": "model",
+                                "parts": [{ "text": format!("{} {}", credential, uri) }]
+                            }
+                        }]
+                    })),
+                )
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+        address
+    }
+
+    async fn analyze_cat(client: &GeminiClient, model: Option<&str>) -> McpResult<String> {
+        let files = FileAccess::new(&[std::path::PathBuf::from("test")]).await;
+        let analysis = client
+            .analyze_image(
+                &AnalyzeImageInput {
+                    image_source: "test/cat_image.jpg".to_string(),
+                    system_prompt: None,
+                    user_prompt: None,
+                    model: model.map(str::to_string),
+                    generation_config: None,
+                },
+                &files,
+                &ProgressReporter::none(),
+            )
+            .await?;
+        Ok(analysis.text)
+    }
+
+    #[tokio::test]
+    async fn test_calls_go_to_the_configured_base_url() {
+        let address = echo_server().await;
+        let client = GeminiClient::new("test-key".to_string())
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1beta/", address))
+            .unwrap();
+
+        assert_eq!(
+            analyze_cat(&client, None).await.unwrap(),
+            format!(
+                "test-key /v1beta/models/{}:generateContent",
+                DEFAULT_ANALYSIS_MODEL
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_content_calls_are_bounded() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let router = axum::Router::new().fallback({
+            let (in_flight, most) = (in_flight.clone(), most.clone());
+            move || async move {
+                most.fetch_max(
+                    in_flight.fetch_add(1, Ordering::SeqCst) + 1,
+                    Ordering::SeqCst,
+                );
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                axum::Json(serde_json::json!({
+                    "candidates": [{ "content": { "parts": [{ "text": "A cat" }] } }]
+                }))
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+        let client = GeminiClient::new("test-key".to_string())
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1beta", address))
+            .unwrap()
+            .with_max_concurrent_requests(2);
+
+        let calls = futures::future::join_all((0..5).map(|_| analyze_cat(&client, None))).await;
+
+        assert!(calls.iter().all(|call| call.is_ok()));
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_uploading_is_reported_once_a_slot_is_free() {
+        let address = echo_server().await;
+        let client = GeminiClient::new("test-key".to_string())
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1beta", address))
+            .unwrap()
+            .with_max_concurrent_requests(1);
+        let files = FileAccess::new(&[std::path::PathBuf::from("test")]).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let progress = ProgressReporter::new(serde_json::json!(1), crate::peer::Peer::new(tx));
+        let input = AnalyzeImageInput {
+            image_source: "test/cat_image.jpg".to_string(),
+            system_prompt: None,
+            user_prompt: None,
+            model: None,
+            generation_config: None,
+        };
+        let mut messages = vec![];
+
+        let slot = client.request_permits.acquire().await.unwrap();
+        let call = client.analyze_image(&input, &files, &progress);
+        tokio::pin!(call);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), &mut call)
+                .await
+                .is_err()
+        );
+        while let Ok(message) = rx.try_recv() {
+            messages.push(message);
+        }
+        assert!(!messages.iter().any(|m| m.contains("Uploading")));
+
+        drop(slot);
+        call.await.unwrap();
+        while let Ok(message) = rx.try_recv() {
+            messages.push(message);
+        }
+        assert!(messages.iter().any(|m| m.contains("Uploading")));
+    }
+
+    #[tokio::test]
+    async fn test_vertex_calls_use_the_project_endpoint_and_access_token() {
+        let address = echo_server().await;
+        let key =
+            crate::vertex::ServiceAccountKey::load(Path::new("test/fake_service_account.json"))
+                .unwrap();
+        let config = VertexConfig::new(
+            "test-project".to_string(),
+            "europe-west4".to_string(),
+            key,
+            Some(&format!("http://{}/token", address)),
+        )
+        .unwrap();
+        let client = GeminiClient::vertex(config)
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1", address))
+            .unwrap();
+
+        // The stand-in rejects contents without a role, as Vertex AI does
+        assert_eq!(
+            analyze_cat(&client, None).await.unwrap(),
+            format!(
+                "Bearer vertex-token /v1/projects/test-project/locations/europe-west4/publishers/google/models/{}:generateContent",
+                DEFAULT_ANALYSIS_MODEL
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_models_are_chosen_per_call_and_validated() {
+        let address = echo_server().await;
+        let client = GeminiClient::new("test-key".to_string())
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1beta", address))
+            .unwrap()
+            .with_default_models("models/gemini-2.5-pro", DEFAULT_IMAGE_MODEL);
+
+        let text = analyze_cat(&client, None).await.unwrap();
+        assert!(text.ends_with("/models/gemini-2.5-pro:generateContent"));
+        let text = analyze_cat(&client, Some("gemini-2.5-flash-lite"))
+            .await
+            .unwrap();
+        assert!(text.ends_with("/models/gemini-2.5-flash-lite:generateContent"));
+
+        // Unknown models refresh the catalog from every page of the models endpoint
+        let text = analyze_cat(&client, Some("gemini-3-pro-preview"))
+            .await
+            .unwrap();
+        assert!(text.ends_with("/models/gemini-3-pro-preview:generateContent"));
+        let error = analyze_cat(&client, Some("gemini-3-pro-previw"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid input: Unknown model 'gemini-3-pro-previw'. Known models: gemini-2.5-flash, gemini-3-pro-preview"
+        );
+
+        // Generators check the model before loading any input image
+        let files = FileAccess::new(&[std::path::PathBuf::from("test")]).await;
+        let edit = EditImageInput {
+            image_source: "test/missing.png".to_string(),
+            system_prompt: None,
+            user_prompt: "Add a hat".to_string(),
+            output_path: "test/hat.png".to_string(),
+            model: Some("gemini-3-pro-previw".to_string()),
+            generation_config: None,
+            include_image: None,
+            overwrite_policy: None,
+        };
+        let error = client
+            .edit_image(&edit, &files, &ProgressReporter::none())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Unknown model"));
+    }
+
+    #[tokio::test]
+    async fn test_vertex_leaves_model_validation_to_the_api() {
+        let address = echo_server().await;
+        let key =
+            crate::vertex::ServiceAccountKey::load(Path::new("test/fake_service_account.json"))
+                .unwrap();
+        let config = VertexConfig::new(
+            "test-project".to_string(),
+            "us-central1".to_string(),
+            key,
+            Some(&format!("http://{}/token", address)),
+        )
+        .unwrap();
+        let client = GeminiClient::vertex(config)
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1", address))
+            .unwrap();
+
+        let text = analyze_cat(&client, Some("gemini-3-pro-preview"))
+            .await
+            .unwrap();
+        assert!(text.ends_with("/models/gemini-3-pro-preview:generateContent"));
+        // No refresh was attempted, so the catalog can still refresh later
+        assert!(client.models.begin_refresh());
+
+        // Vertex AI has no model listing, so `list_models` isn't offered
+        let tools = crate::gemini_tools::gemini_tools(Some(Arc::new(client)));
+        assert!(tools.iter().all(|tool| tool.name() != "list_models"));
+    }
+
+    #[test]
+    fn test_generation_config_defaults_and_ranges() {
+        let defaults = GenerationConfig {
+            temperature: Some(1.0),
+            seed: Some(42),
+            ..GenerationConfig::default()
+        };
+        let config = GenerationConfig {
+            temperature: Some(0.2),
+            top_k: Some(40),
+            ..GenerationConfig::default()
+        }
+        .or(&defaults);
+        assert_eq!(config.temperature, Some(0.2));
+        assert_eq!(config.seed, Some(42));
+        assert!(config.validate().is_ok());
+
+        let request = GeminiRequest::new(
+            vec![],
+            Some(GenerationConfig {
+                max_output_tokens: Some(1024),
+                ..config
+            }),
+        );
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["generationConfig"],
+            serde_json::json!({ "temperature": 0.2, "topK": 40, "seed": 42, "maxOutputTokens": 1024 })
+        );
+        assert!(
+            serde_json::to_value(GeminiRequest::new(vec![], None))
+                .unwrap()
+                .get("generationConfig")
+                .is_none()
+        );
+
+        let invalid = [
+            GenerationConfig {
+                temperature: Some(2.5),
+                ..GenerationConfig::default()
+            },
+            GenerationConfig {
+                top_p: Some(-0.1),
+                ..GenerationConfig::default()
+            },
+            GenerationConfig {
+                top_k: Some(0),
+                ..GenerationConfig::default()
+            },
+            GenerationConfig {
+                max_output_tokens: Some(0),
+                ..GenerationConfig::default()
+            },
+        ];
+        for config in invalid {
+            assert!(matches!(config.validate(), Err(McpError::InvalidInput(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generation_config_is_sent_and_reported() {
+        let address = echo_server().await;
+        let client = GeminiClient::new("test-key".to_string())
+            .unwrap()
+            .with_base_url(&format!("http://{}/v1beta", address))
+            .unwrap()
+            .with_generation_defaults(GenerationConfig {
+                seed: Some(7),
+                ..GenerationConfig::default()
+            });
+        let files = FileAccess::new(&[std::path::PathBuf::from("test")]).await;
+        let input = |temperature: f64| AnalyzeImageInput {
+            image_source: "test/cat_image.jpg".to_string(),
+            system_prompt: None,
+            user_prompt: None,
+            model: None,
+            generation_config: Some(GenerationConfig {
+                temperature: Some(temperature),
+                ..GenerationConfig::default()
+            }),
+        };
+
+        let analysis = client
+            .analyze_image(&input(0.0), &files, &ProgressReporter::none())
+            .await
+            .unwrap();
+        assert_eq!(
+            analysis.generation_config,
+            Some(GenerationConfig {
+                temperature: Some(0.0),
+                seed: Some(7),
+                ..GenerationConfig::default()
+            })
+        );
+
+        let error = client
+            .analyze_image(&input(3.0), &files, &ProgressReporter::none())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid input: temperature must be between 0.0 and 2.0, got 3"
+        );
+    }
 }
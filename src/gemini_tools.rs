@@ -0,0 +1,552 @@
+use crate::error::{McpError, McpResult};
+use crate::gemini_client::{
+    AnalyzeImageInput, ComposeImagesInput, EditImageInput, GeminiClient, GenerateImageInput,
+    GeneratedImage, GeneratedImageOutput, ImageAnalysis, InpaintImageInput, RefineImageInput,
+    StyleTransferInput,
+};
+use crate::models::{ListModelsInput, ListModelsOutput, ModelSummary};
+use crate::progress::ProgressReporter;
+use crate::roots::FileAccess;
+use crate::tools::{Tool, ToolAnnotations, ToolContext, ToolError, parse_arguments};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// The built-in Gemini tools, in the order `tools/list` reports them.
+pub fn gemini_tools(client: Option<Arc<GeminiClient>>) -> Vec<Arc<dyn Tool>> {
+    let mut tools: Vec<Arc<dyn Tool>> = vec![
+        Arc::new(AnalyzeImageTool {
+            client: client.clone(),
+        }),
+        Arc::new(GeneratorTool::new(client.clone(), GenerateImage)),
+        Arc::new(GeneratorTool::new(client.clone(), EditImage)),
+        Arc::new(GeneratorTool::new(client.clone(), InpaintImage)),
+        Arc::new(GeneratorTool::new(client.clone(), StyleTransfer)),
+        Arc::new(GeneratorTool::new(client.clone(), ComposeImages)),
+        Arc::new(GeneratorTool::new(client.clone(), RefineImage)),
+    ];
+    // Vertex AI has no model listing
+    if !client.as_deref().is_some_and(GeminiClient::is_vertex) {
+        tools.push(Arc::new(ListModelsTool { client }));
+    }
+    tools
+}
+
+/// Every tool calls the Gemini API, so all are open-world. The generators write
+/// (and may overwrite) `output_path` and return a different image each time.
+const GENERATOR_ANNOTATIONS: ToolAnnotations = ToolAnnotations {
+    read_only: false,
+    destructive: true,
+    idempotent: false,
+    open_world: true,
+};
+
+fn require_client(client: &Option<Arc<GeminiClient>>) -> Result<&GeminiClient, ToolError> {
+    client.as_deref().ok_or_else(|| {
+        ToolError::InvalidRequest(McpError::ConfigurationError(
+            "No Gemini API key or Vertex AI service account configured".to_string(),
+        ))
+    })
+}
+
+fn schema_of<T: JsonSchema>() -> Value {
+    serde_json::to_value(schemars::schema_for!(T)).unwrap()
+}
+
+struct AnalyzeImageTool {
+    client: Option<Arc<GeminiClient>>,
+}
+
+#[async_trait]
+impl Tool for AnalyzeImageTool {
+    fn name(&self) -> &str {
+        "analyze_image"
+    }
+
+    fn title(&self) -> &str {
+        "Analyze Image"
+    }
+
+    fn description(&self) -> &str {
+        "Analyze an image using Google's Gemini API. Supports both URLs (http/https) and local file paths."
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_of::<AnalyzeImageInput>()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(schema_of::<ImageAnalysis>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations {
+            read_only: true,
+            destructive: false,
+            idempotent: true,
+            open_world: true,
+        }
+    }
+
+    async fn call(&self, arguments: Value, context: &ToolContext<'_>) -> Result<Value, ToolError> {
+        let client = require_client(&self.client)?;
+        let input: AnalyzeImageInput = parse_arguments(self.name(), arguments)?;
+
+        let analysis = client
+            .analyze_image(&input, &context.file_access().await, context.progress())
+            .await
+            .inspect_err(|e| error!("Failed to analyze image '{}': {}", input.image_source, e))?;
+
+        info!("Successfully analyzed image: {}", input.image_source);
+        Ok(json!({
+            "content": [
+                {
+                    "type": "text",
+                    "text": analysis.text
+                }
+            ],
+            "structuredContent": analysis
+        }))
+    }
+}
+
+struct ListModelsTool {
+    client: Option<Arc<GeminiClient>>,
+}
+
+#[async_trait]
+impl Tool for ListModelsTool {
+    fn name(&self) -> &str {
+        "list_models"
+    }
+
+    fn title(&self) -> &str {
+        "List Models"
+    }
+
+    fn description(&self) -> &str {
+        "List the Gemini models available to this server, optionally filtered by API method or by input/output modality (e.g. output_modality \"image\" for models that generate images). Pass a name as the model argument of the other tools."
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_of::<ListModelsInput>()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(schema_of::<ListModelsOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations {
+            read_only: true,
+            destructive: false,
+            idempotent: true,
+            open_world: true,
+        }
+    }
+
+    async fn call(&self, arguments: Value, _context: &ToolContext<'_>) -> Result<Value, ToolError> {
+        let client = require_client(&self.client)?;
+        // Every filter is optional, so a call may send no arguments at all
+        let input: ListModelsInput = if arguments.is_null() {
+            ListModelsInput::default()
+        } else {
+            parse_arguments(self.name(), arguments)?
+        };
+
+        let models = client
+            .list_models()
+            .await
+            .inspect_err(|e| error!("Failed to list models: {}", e))?;
+        let models: Vec<ModelSummary> = models
+            .iter()
+            .map(ModelSummary::from)
+            .filter(|model| input.matches(model))
+            .collect();
+
+        let mut text = format!("{} models:", models.len());
+        for model in &models {
+            text.push_str(&format!(
+                "\n- {} ({}): {} input / {} output tokens, outputs {}",
+                model.name,
+                model.display_name,
+                token_limit(model.input_token_limit),
+                token_limit(model.output_token_limit),
+                model
+                    .output_modalities
+                    .iter()
+                    .map(|modality| modality.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            ));
+        }
+        Ok(json!({
+            "content": [
+                {
+                    "type": "text",
+                    "text": text
+                }
+            ],
+            "structuredContent": ListModelsOutput { models }
+        }))
+    }
+}
+
+fn token_limit(limit: Option<u64>) -> String {
+    limit.map_or_else(|| "?".to_string(), |limit| limit.to_string())
+}
+
+/// One of the tools that derive a new image and save it to `output_path`.
+#[async_trait]
+trait ImageGenerator: Send + Sync {
+    type Input: DeserializeOwned + JsonSchema + Send + Sync;
+
+    fn name(&self) -> &'static str;
+    fn title(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage>;
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool>;
+
+    /// Result text for an image saved to `path`
+    fn success(&self, path: &str) -> String;
+
+    /// Logged when generation fails
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String;
+}
+
+struct GeneratorTool<G> {
+    client: Option<Arc<GeminiClient>>,
+    generator: G,
+}
+
+impl<G> GeneratorTool<G> {
+    fn new(client: Option<Arc<GeminiClient>>, generator: G) -> Self {
+        Self { client, generator }
+    }
+}
+
+#[async_trait]
+impl<G: ImageGenerator> Tool for GeneratorTool<G> {
+    fn name(&self) -> &str {
+        self.generator.name()
+    }
+
+    fn title(&self) -> &str {
+        self.generator.title()
+    }
+
+    fn description(&self) -> &str {
+        self.generator.description()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_of::<G::Input>()
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(schema_of::<GeneratedImageOutput>())
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        GENERATOR_ANNOTATIONS
+    }
+
+    async fn call(&self, arguments: Value, context: &ToolContext<'_>) -> Result<Value, ToolError> {
+        let client = require_client(&self.client)?;
+        let input: G::Input = parse_arguments(self.name(), arguments)?;
+
+        let image = self
+            .generator
+            .generate(
+                client,
+                &input,
+                &context.file_access().await,
+                context.progress(),
+            )
+            .await
+            .inspect_err(|e| error!("{}", self.generator.failure(&input, e)))?;
+
+        let text = self.generator.success(&image.file_path);
+        info!("{}", text);
+        Ok(context
+            .generated_image_result(text, image, self.generator.include_image(&input))
+            .await)
+    }
+}
+
+struct GenerateImage;
+
+#[async_trait]
+impl ImageGenerator for GenerateImage {
+    type Input = GenerateImageInput;
+
+    fn name(&self) -> &'static str {
+        "generate_image"
+    }
+
+    fn title(&self) -> &'static str {
+        "Generate Image"
+    }
+
+    fn description(&self) -> &'static str {
+        "Generate an image using Google's Gemini API with optional system prompt and required user prompt."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.generate_image(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Image successfully generated and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to generate image with prompt '{}': {}",
+            input.user_prompt, error
+        )
+    }
+}
+
+struct EditImage;
+
+#[async_trait]
+impl ImageGenerator for EditImage {
+    type Input = EditImageInput;
+
+    fn name(&self) -> &'static str {
+        "edit_image"
+    }
+
+    fn title(&self) -> &'static str {
+        "Edit Image"
+    }
+
+    fn description(&self) -> &'static str {
+        "Edit an existing image using Google's Gemini API by providing both an input image and a text prompt describing the desired changes."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.edit_image(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Image successfully edited and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to edit image '{}' with prompt '{}': {}",
+            input.image_source, input.user_prompt, error
+        )
+    }
+}
+
+struct InpaintImage;
+
+#[async_trait]
+impl ImageGenerator for InpaintImage {
+    type Input = InpaintImageInput;
+
+    fn name(&self) -> &'static str {
+        "inpaint_image"
+    }
+
+    fn title(&self) -> &'static str {
+        "Inpaint Image"
+    }
+
+    fn description(&self) -> &'static str {
+        "Inpaint/modify specific regions of an image using semantic masking. Supports focusing on specific elements or regions."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.inpaint_image(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Image successfully inpainted and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to inpaint image '{}' with prompt '{}': {}",
+            input.image_source, input.user_prompt, error
+        )
+    }
+}
+
+struct StyleTransfer;
+
+#[async_trait]
+impl ImageGenerator for StyleTransfer {
+    type Input = StyleTransferInput;
+
+    fn name(&self) -> &'static str {
+        "style_transfer"
+    }
+
+    fn title(&self) -> &'static str {
+        "Style Transfer"
+    }
+
+    fn description(&self) -> &'static str {
+        "Transfer the artistic style from one image to another using Google's Gemini API."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.style_transfer(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Style transfer completed and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to apply style transfer from '{}' to '{}': {}",
+            input.style_image, input.source_image, error
+        )
+    }
+}
+
+struct ComposeImages;
+
+#[async_trait]
+impl ImageGenerator for ComposeImages {
+    type Input = ComposeImagesInput;
+
+    fn name(&self) -> &'static str {
+        "compose_images"
+    }
+
+    fn title(&self) -> &'static str {
+        "Compose Images"
+    }
+
+    fn description(&self) -> &'static str {
+        "Compose multiple images into a single new image using Google's Gemini API."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.compose_images(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Images successfully composed and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to compose images with primary '{}' and {} secondary images: {}",
+            input.primary_image,
+            input.secondary_images.len(),
+            error
+        )
+    }
+}
+
+struct RefineImage;
+
+#[async_trait]
+impl ImageGenerator for RefineImage {
+    type Input = RefineImageInput;
+
+    fn name(&self) -> &'static str {
+        "refine_image"
+    }
+
+    fn title(&self) -> &'static str {
+        "Refine Image"
+    }
+
+    fn description(&self) -> &'static str {
+        "Iteratively refine an image with conversation history for progressive improvement using Google's Gemini API."
+    }
+
+    async fn generate(
+        &self,
+        client: &GeminiClient,
+        input: &Self::Input,
+        files: &FileAccess,
+        progress: &ProgressReporter,
+    ) -> McpResult<GeneratedImage> {
+        client.refine_image(input, files, progress).await
+    }
+
+    fn include_image(&self, input: &Self::Input) -> Option<bool> {
+        input.include_image
+    }
+
+    fn success(&self, path: &str) -> String {
+        format!("Image successfully refined and saved to: {}", path)
+    }
+
+    fn failure(&self, input: &Self::Input, error: &McpError) -> String {
+        format!(
+            "Failed to refine image '{}' with prompt '{}': {}",
+            input.image_source, input.user_prompt, error
+        )
+    }
+}
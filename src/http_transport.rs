@@ -0,0 +1,813 @@
+use crate::jsonrpc::{JsonRpcHandler, JsonRpcPayload, SUPPORTED_PROTOCOL_VERSIONS};
+use crate::peer::Peer;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+use tracing::{info, warn};
+
+/// Path of the single MCP endpoint
+pub const MCP_ENDPOINT: &str = "/mcp";
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+
+/// Sessions without requests or an open stream for this long are dropped
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// Sessions kept at once; `initialize` is refused beyond this
+pub const DEFAULT_MAX_SESSIONS: usize = 1000;
+/// Server messages held for a session while no GET stream reads them; more are dropped
+const SERVER_MESSAGE_BUFFER: usize = 256;
+/// Longest time between sweeps for idle sessions
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+type HttpError = (StatusCode, &'static str);
+
+type ServerMessages = Arc<Mutex<Option<mpsc::Receiver<String>>>>;
+
+type Sessions = Mutex<HashMap<String, Arc<HttpSession>>>;
+
+/// Bounds on the sessions the HTTP transport keeps.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionLimits {
+    /// How long a session without requests or an open GET stream survives
+    pub idle_timeout: Duration,
+    /// Sessions kept at once
+    pub max_sessions: usize,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        }
+    }
+}
+
+struct HttpSession {
+    handler: Arc<JsonRpcHandler>,
+    /// Messages not tied to a request, delivered on the session's GET stream.
+    /// The receiver is parked here while no stream is open.
+    server_messages: ServerMessages,
+    last_seen: Mutex<Instant>,
+    /// Cancelled when the session ends, closing its GET stream
+    closed: CancellationToken,
+}
+
+impl HttpSession {
+    fn is_idle(&self, timeout: Duration) -> bool {
+        // The receiver is taken while a GET stream is open
+        let stream_open = self.server_messages.lock().unwrap().is_none();
+        !stream_open && self.last_seen.lock().unwrap().elapsed() >= timeout
+    }
+}
+
+/// Ends the sessions that have been idle for `idle_timeout`.
+fn expire_idle_sessions(sessions: &mut HashMap<String, Arc<HttpSession>>, idle_timeout: Duration) {
+    sessions.retain(|session_id, session| {
+        if !session.is_idle(idle_timeout) {
+            return true;
+        }
+        info!("Expired idle HTTP session {}", session_id);
+        session.closed.cancel();
+        false
+    });
+}
+
+/// Expires idle sessions in the background, so sessions nobody comes back to
+/// are dropped even when no new ones are created. Stops with the router.
+fn spawn_session_sweeper(sessions: Weak<Sessions>, idle_timeout: Duration) {
+    let mut sweep = tokio::time::interval(idle_timeout.min(SESSION_SWEEP_INTERVAL));
+    tokio::spawn(async move {
+        loop {
+            sweep.tick().await;
+            let Some(sessions) = sessions.upgrade() else {
+                return;
+            };
+            expire_idle_sessions(&mut sessions.lock().unwrap(), idle_timeout);
+        }
+    });
+}
+
+#[derive(Clone)]
+struct AppState {
+    base_handler: Arc<JsonRpcHandler>,
+    sessions: Arc<Sessions>,
+    limits: SessionLimits,
+}
+
+impl AppState {
+    fn create_session(&self) -> Result<(String, Arc<HttpSession>), HttpError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        expire_idle_sessions(&mut sessions, self.limits.idle_timeout);
+        if sessions.len() >= self.limits.max_sessions {
+            warn!(
+                "Refused a new HTTP session: {} sessions are open",
+                sessions.len()
+            );
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "Too many open sessions"));
+        }
+
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = mpsc::channel(SERVER_MESSAGE_BUFFER);
+        let session = Arc::new(HttpSession {
+            handler: Arc::new(self.base_handler.new_session().with_peer(Peer::bounded(tx))),
+            server_messages: Arc::new(Mutex::new(Some(rx))),
+            last_seen: Mutex::new(Instant::now()),
+            closed: CancellationToken::new(),
+        });
+        let handler = Arc::clone(&session.handler);
+        let closed = session.closed.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = handler.forward_tool_list_changes() => {}
+                _ = closed.cancelled() => {}
+            }
+        });
+
+        sessions.insert(session_id.clone(), Arc::clone(&session));
+        info!("Created HTTP session {}", session_id);
+        Ok((session_id, session))
+    }
+
+    /// Looks up the session named by the `Mcp-Session-Id` header. A session that
+    /// has been idle too long is ended instead.
+    fn session(&self, headers: &HeaderMap) -> Result<(String, Arc<HttpSession>), HttpError> {
+        let Some(session_id) = headers.get(SESSION_HEADER).and_then(|v| v.to_str().ok()) else {
+            return Err((StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header"));
+        };
+
+        let session = self.sessions.lock().unwrap().get(session_id).cloned();
+        match session {
+            Some(session) if session.is_idle(self.limits.idle_timeout) => {
+                info!("Expired idle HTTP session {}", session_id);
+                self.remove_session(session_id);
+                Err((StatusCode::NOT_FOUND, "Unknown or expired session"))
+            }
+            Some(session) => {
+                *session.last_seen.lock().unwrap() = Instant::now();
+                Ok((session_id.to_string(), session))
+            }
+            None => Err((StatusCode::NOT_FOUND, "Unknown or expired session")),
+        }
+    }
+
+    fn remove_session(&self, session_id: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(session_id) {
+            session.closed.cancel();
+        }
+    }
+}
+
+/// Builds the Streamable HTTP router. Every session gets its own handler created
+/// from `handler`, so they share the Gemini client and concurrency limit.
+pub fn router(handler: JsonRpcHandler) -> Router {
+    router_with_limits(handler, SessionLimits::default())
+}
+
+/// Like `router`, with custom bounds on the sessions kept.
+/// Must be called within a Tokio runtime, which runs the idle-session sweep.
+pub fn router_with_limits(handler: JsonRpcHandler, limits: SessionLimits) -> Router {
+    let state = AppState {
+        base_handler: Arc::new(handler),
+        sessions: Arc::new(Mutex::new(HashMap::new())),
+        limits,
+    };
+    spawn_session_sweeper(Arc::downgrade(&state.sessions), limits.idle_timeout);
+
+    Router::new()
+        .route(
+            MCP_ENDPOINT,
+            axum::routing::post(handle_post)
+                .get(handle_get)
+                .delete(handle_delete),
+        )
+        .with_state(state)
+}
+
+pub async fn serve(handler: JsonRpcHandler, addr: SocketAddr) -> anyhow::Result<()> {
+    if !addr.ip().is_loopback() {
+        warn!(
+            "HTTP transport listening on non-loopback address {}; put it behind an authenticating proxy",
+            addr
+        );
+    }
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!(
+        "Serving MCP Streamable HTTP on http://{}{}",
+        listener.local_addr()?,
+        MCP_ENDPOINT
+    );
+    axum::serve(listener, router(handler)).await?;
+    Ok(())
+}
+
+async fn handle_post(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(rejection) = check_origin(&headers) {
+        return rejection.into_response();
+    }
+
+    let payload = match JsonRpcPayload::parse(&body) {
+        Ok(payload) => payload,
+        Err(error_response) => {
+            return json_response(StatusCode::BAD_REQUEST, &error_response.to_json());
+        }
+    };
+
+    let lookup = if payload.is_initialize() && !headers.contains_key(SESSION_HEADER) {
+        state.create_session()
+    } else {
+        check_protocol_version(&headers).and_then(|()| state.session(&headers))
+    };
+    let (session_id, session) = match lookup {
+        Ok(found) => found,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    // Notifications only need to be acknowledged
+    if payload.is_notification_only() {
+        session.handler.handle_payload(payload).await;
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    let mut response = if accepts_event_stream(&headers) {
+        // Stream notifications for these requests (e.g. progress) followed by the reply.
+        // A client that disconnects drops the stream, which cancels the requests.
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handler = Arc::clone(&session.handler);
+        tokio::spawn(async move {
+            tokio::select! {
+                reply = handler.handle_payload_with_sender(payload, tx.clone()) => {
+                    if let Some(reply) = reply {
+                        let _ = tx.send(reply.to_json());
+                    }
+                }
+                _ = tx.closed() => {
+                    info!("Client disconnected from a response stream, cancelling its requests");
+                }
+            }
+        });
+
+        let events = UnboundedReceiverStream::new(rx)
+            .map(|message| Ok::<_, Infallible>(Event::default().event("message").data(message)));
+        Sse::new(events).into_response()
+    } else {
+        match session.handler.handle_payload(payload).await {
+            Some(reply) => json_response(StatusCode::OK, &reply.to_json()),
+            None => StatusCode::ACCEPTED.into_response(),
+        }
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&session_id) {
+        response.headers_mut().insert(SESSION_HEADER, value);
+    }
+    response
+}
+
+async fn handle_get(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = check_origin(&headers) {
+        return rejection.into_response();
+    }
+    if !accepts_event_stream(&headers) {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "GET requires Accept: text/event-stream",
+        )
+            .into_response();
+    }
+
+    let session = match check_protocol_version(&headers).and_then(|()| state.session(&headers)) {
+        Ok((_, session)) => session,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let Some(receiver) = session.server_messages.lock().unwrap().take() else {
+        return (
+            StatusCode::CONFLICT,
+            "A stream is already open for this session",
+        )
+            .into_response();
+    };
+
+    let events = SessionStream {
+        receiver: Some(receiver),
+        slot: Arc::clone(&session.server_messages),
+        closed: Box::pin(session.closed.clone().cancelled_owned()),
+    }
+    .map(|message| Ok::<_, Infallible>(Event::default().event("message").data(message)));
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn handle_delete(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = check_origin(&headers) {
+        return rejection.into_response();
+    }
+
+    match check_protocol_version(&headers).and_then(|()| state.session(&headers)) {
+        Ok((session_id, _)) => {
+            state.remove_session(&session_id);
+            info!("Terminated HTTP session {}", session_id);
+            StatusCode::OK.into_response()
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Server-to-client stream of a session. Dropping it (client disconnect) parks
+/// the receiver again so undelivered messages reach the next GET stream; the
+/// stream ends for good when the session does.
+struct SessionStream {
+    receiver: Option<mpsc::Receiver<String>>,
+    slot: ServerMessages,
+    closed: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl Stream for SessionStream {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<String>> {
+        if self.closed.as_mut().poll(cx).is_ready() {
+            self.receiver = None;
+            return Poll::Ready(None);
+        }
+        match self.receiver.as_mut() {
+            Some(receiver) => receiver.poll_recv(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl Drop for SessionStream {
+    fn drop(&mut self) {
+        if let Some(receiver) = self.receiver.take() {
+            *self.slot.lock().unwrap() = Some(receiver);
+        }
+    }
+}
+
+fn json_response(status: StatusCode, body: &str) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Rejects requests after `initialize` whose `MCP-Protocol-Version` we don't speak.
+/// A missing header is accepted, as the spec asks for older clients.
+fn check_protocol_version(headers: &HeaderMap) -> Result<(), HttpError> {
+    let Some(version) = headers.get(PROTOCOL_VERSION_HEADER) else {
+        return Ok(());
+    };
+
+    if version
+        .to_str()
+        .is_ok_and(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(&version))
+    {
+        Ok(())
+    } else {
+        warn!("Rejected request with MCP-Protocol-Version {:?}", version);
+        Err((StatusCode::BAD_REQUEST, "Unsupported MCP-Protocol-Version"))
+    }
+}
+
+/// Rejects browser requests from non-local origins to prevent DNS rebinding.
+fn check_origin(headers: &HeaderMap) -> Result<(), HttpError> {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return Ok(());
+    };
+
+    let is_local = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| reqwest::Url::parse(origin).ok())
+        .and_then(|url| url.host_str().map(str::to_string))
+        .is_some_and(|host| matches!(host.as_str(), "localhost" | "127.0.0.1" | "[::1]"));
+
+    if is_local {
+        Ok(())
+    } else {
+        warn!("Rejected request from origin {:?}", origin);
+        Err((StatusCode::FORBIDDEN, "Origin not allowed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{Tool, ToolContext, ToolError};
+    use async_trait::async_trait;
+    use serde_json::{Value, json};
+    use tokio::sync::Notify;
+
+    async fn spawn_server() -> String {
+        spawn_server_with_limits(SessionLimits::default()).await
+    }
+
+    async fn spawn_server_with_limits(limits: SessionLimits) -> String {
+        serve_router(router_with_limits(JsonRpcHandler::new(None), limits)).await
+    }
+
+    async fn serve_router(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+        format!("http://{}{}", addr, MCP_ENDPOINT)
+    }
+
+    /// Never finishes; notifies `abandoned` once its call is dropped.
+    struct HangingTool {
+        abandoned: Arc<Notify>,
+    }
+
+    struct NotifyOnDrop(Arc<Notify>);
+
+    impl Drop for NotifyOnDrop {
+        fn drop(&mut self) {
+            self.0.notify_one();
+        }
+    }
+
+    #[async_trait]
+    impl Tool for HangingTool {
+        fn name(&self) -> &str {
+            "hang"
+        }
+
+        fn title(&self) -> &str {
+            "Hang"
+        }
+
+        fn description(&self) -> &str {
+            "Never returns"
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({ "type": "object" })
+        }
+
+        async fn call(
+            &self,
+            _arguments: Value,
+            _context: &ToolContext<'_>,
+        ) -> Result<Value, ToolError> {
+            let _guard = NotifyOnDrop(Arc::clone(&self.abandoned));
+            std::future::pending().await
+        }
+    }
+
+    async fn tools_list(client: &reqwest::Client, url: &str, session_id: &str) -> StatusCode {
+        client
+            .post(url)
+            .header("Accept", "application/json")
+            .header(SESSION_HEADER, session_id)
+            .json(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn initialize(client: &reqwest::Client, url: &str) -> String {
+        let response = client
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        response.headers()[SESSION_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_creates_session() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json")
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().contains_key(SESSION_HEADER));
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["id"], 1);
+        assert_eq!(body["result"]["serverInfo"]["name"], "gemini-image-mcp");
+    }
+
+    #[tokio::test]
+    async fn test_requests_require_known_session() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let tools_list = json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"});
+
+        let response = client.post(&url).json(&tools_list).send().await.unwrap();
+        assert_eq!(response.status(), 400);
+
+        let response = client
+            .post(&url)
+            .header(SESSION_HEADER, "not-a-session")
+            .json(&tools_list)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_request_streams_response_as_sse() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json, text/event-stream")
+            .header(SESSION_HEADER, &session_id)
+            .json(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/event-stream")
+        );
+        let body = response.text().await.unwrap();
+        let data = body
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        let message: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(message["id"], 2);
+        assert!(message["result"]["tools"].is_array());
+    }
+
+    #[tokio::test]
+    async fn test_disconnecting_from_a_response_stream_cancels_the_request() {
+        let abandoned = Arc::new(Notify::new());
+        let handler = JsonRpcHandler::new(None).with_tool(HangingTool {
+            abandoned: Arc::clone(&abandoned),
+        });
+        let url = serve_router(router(handler)).await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json, text/event-stream")
+            .header(SESSION_HEADER, &session_id)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "tools/call",
+                "params": { "name": "hang", "arguments": {} }
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        drop(response);
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), abandoned.notified())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notification_is_accepted() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .post(&url)
+            .header(SESSION_HEADER, &session_id)
+            .json(&json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 202);
+        assert!(response.text().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_terminates_session() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .delete(&url)
+            .header(SESSION_HEADER, &session_id)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json")
+            .header(SESSION_HEADER, &session_id)
+            .json(&json!({"jsonrpc": "2.0", "id": 3, "method": "tools/list"}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_delete_closes_the_session_stream() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+        let stream = client
+            .get(&url)
+            .header("Accept", "text/event-stream")
+            .header(SESSION_HEADER, &session_id)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(stream.status(), 200);
+
+        let response = client
+            .delete(&url)
+            .header(SESSION_HEADER, &session_id)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        // The body ends instead of waiting for messages that can no longer come
+        tokio::time::timeout(std::time::Duration::from_secs(5), stream.text())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_expire() {
+        let url = spawn_server_with_limits(SessionLimits {
+            idle_timeout: std::time::Duration::from_millis(100),
+            ..SessionLimits::default()
+        })
+        .await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+        assert_eq!(tools_list(&client, &url, &session_id).await, 200);
+
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(tools_list(&client, &url, &session_id).await, 404);
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_are_swept() {
+        let idle_timeout = std::time::Duration::from_millis(100);
+        let state = AppState {
+            base_handler: Arc::new(JsonRpcHandler::new(None)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            limits: SessionLimits {
+                idle_timeout,
+                ..SessionLimits::default()
+            },
+        };
+        let (_, session) = state.create_session().unwrap();
+        spawn_session_sweeper(Arc::downgrade(&state.sessions), idle_timeout);
+
+        // Nothing else touches the sessions; the sweep alone ends it
+        tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            session.closed.cancelled(),
+        )
+        .await
+        .unwrap();
+        assert!(state.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_capped() {
+        let url = spawn_server_with_limits(SessionLimits {
+            max_sessions: 1,
+            ..SessionLimits::default()
+        })
+        .await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .post(&url)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+
+        // Ending a session makes room for the next one
+        client
+            .delete(&url)
+            .header(SESSION_HEADER, &session_id)
+            .send()
+            .await
+            .unwrap();
+        initialize(&client, &url).await;
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_protocol_version_is_rejected() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+        let request = |version: &str| {
+            client
+                .post(&url)
+                .header("Accept", "application/json")
+                .header(SESSION_HEADER, &session_id)
+                .header(PROTOCOL_VERSION_HEADER, version)
+                .json(&json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}))
+                .send()
+        };
+
+        assert_eq!(request("2025-06-18").await.unwrap().status(), 200);
+        assert_eq!(request("1999-01-01").await.unwrap().status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_origin_is_rejected() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .post(&url)
+            .header("Origin", "https://evil.example.com")
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+    }
+
+    #[tokio::test]
+    async fn test_batch_request_returns_array() {
+        let url = spawn_server().await;
+        let client = reqwest::Client::new();
+        let session_id = initialize(&client, &url).await;
+
+        let response = client
+            .post(&url)
+            .header("Accept", "application/json")
+            .header(SESSION_HEADER, &session_id)
+            .json(&json!([
+                {"jsonrpc": "2.0", "id": 1, "method": "tools/list"},
+                {"jsonrpc": "2.0", "method": "notifications/initialized"},
+                {"jsonrpc": "2.0", "id": 2, "method": "unknown/method"}
+            ]))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        let responses = body.as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[1]["error"]["code"], -32601);
+    }
+}
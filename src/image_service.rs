@@ -1,10 +1,40 @@
 use crate::error::{McpError, McpResult};
+use crate::roots::FileAccess;
 use crate::validation::{ImageSourceValidator, Validator, get_mime_type_from_extension};
 use base64::{Engine as _, engine::general_purpose};
-use std::path::Path;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tracing::warn;
 
+static PARTIAL_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// How far a save got, shared by the blocking writer and the future awaiting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SaveState {
+    Writing,
+    Abandoned,
+    /// Renamed into place
+    Finished,
+}
+
+/// Marks an in-progress save as abandoned when the owning future is dropped,
+/// e.g. because the request was cancelled.
+struct AbandonOnDrop(Arc<Mutex<SaveState>>);
+
+impl Drop for AbandonOnDrop {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        if *state == SaveState::Writing {
+            *state = SaveState::Abandoned;
+        }
+    }
+}
+
 pub struct ImageService {
     client: reqwest::Client,
     validator: ImageSourceValidator,
@@ -23,13 +53,20 @@ impl ImageService {
         })
     }
 
-    pub async fn fetch_and_encode(&self, source: &str) -> McpResult<(String, String)> {
+    /// Loads an image from a URL or a local file. Local files must lie inside
+    /// one of the roots of `files`.
+    pub async fn fetch_and_encode(
+        &self,
+        source: &str,
+        files: &FileAccess,
+    ) -> McpResult<(String, String)> {
         self.validator.validate(&source.to_string())?;
 
         let (mime_type, image_bytes) = if self.is_url(source) {
             self.fetch_from_url(source).await?
         } else {
-            self.fetch_from_file(source).await?
+            let path = files.check_read(source).await?;
+            self.fetch_from_file(&path.to_string_lossy()).await?
         };
 
         if image_bytes.is_empty() {
@@ -46,6 +83,43 @@ impl ImageService {
         Ok((mime_type, encoded))
     }
 
+    /// Writes the image to a temporary file next to `output_path` and renames it into
+    /// place, so a cancelled request never leaves a partial file at `output_path`.
+    ///
+    /// The cancellation check and the rename happen under the lock the drop of this
+    /// future takes, so a cancel either stops the save before the rename or comes
+    /// after it. In the second case the complete image stays at `output_path` even
+    /// though the request gets no response.
+    pub async fn save_image(&self, output_path: &str, bytes: Vec<u8>) -> std::io::Result<()> {
+        let output_path = PathBuf::from(output_path);
+        let state = Arc::new(Mutex::new(SaveState::Writing));
+        let _guard = AbandonOnDrop(Arc::clone(&state));
+
+        // The blocking write keeps running if this future is dropped, so it checks
+        // whether the save was abandoned before publishing the file
+        tokio::task::spawn_blocking(move || {
+            let partial_path = partial_path_for(&output_path);
+            std::fs::write(&partial_path, &bytes)?;
+
+            let mut state = state.lock().unwrap();
+            if *state == SaveState::Abandoned {
+                let _ = std::fs::remove_file(&partial_path);
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Interrupted,
+                    "image save was cancelled",
+                ));
+            }
+
+            std::fs::rename(&partial_path, &output_path).inspect_err(|_| {
+                let _ = std::fs::remove_file(&partial_path);
+            })?;
+            *state = SaveState::Finished;
+            Ok(())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
     fn is_url(&self, source: &str) -> bool {
         source.starts_with("http://") || source.starts_with("https://")
     }
@@ -130,3 +204,152 @@ impl ImageService {
         Ok(get_mime_type_from_extension(url))
     }
 }
+
+/// Reads the pixel dimensions from the image header without decoding the image.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Shrinks an image so neither side exceeds `max_dimension` pixels, re-encoding it
+/// as PNG. Returns `None` when the image already fits.
+pub fn downscale_image(data: &[u8], max_dimension: u32) -> McpResult<Option<Vec<u8>>> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| McpError::ContentTypeError(format!("Cannot decode image: {}", e)))?;
+
+    if image.width().max(image.height()) <= max_dimension {
+        return Ok(None);
+    }
+
+    let resized = image.resize(max_dimension, max_dimension, FilterType::Triangle);
+    let mut encoded = Cursor::new(Vec::new());
+    resized
+        .write_to(&mut encoded, ImageFormat::Png)
+        .map_err(|e| McpError::ContentTypeError(format!("Cannot encode image: {}", e)))?;
+    Ok(Some(encoded.into_inner()))
+}
+
+fn partial_path_for(output_path: &Path) -> PathBuf {
+    let file_name = output_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let unique = PARTIAL_FILE_COUNTER.fetch_add(1, Ordering::SeqCst);
+    output_path.with_file_name(format!(
+        ".{}.{}-{}.partial",
+        file_name,
+        std::process::id(),
+        unique
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_save_image_leaves_no_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let service = ImageService::new().unwrap();
+
+        service
+            .save_image(output_path.to_str().unwrap(), vec![1, 2, 3])
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&output_path).unwrap(), vec![1, 2, 3]);
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_image_dropped_before_completion_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        let service = ImageService::new().unwrap();
+
+        {
+            let save = service.save_image(output_path.to_str().unwrap(), vec![0; 1024]);
+            // Poll once so the blocking write is started, then drop the future
+            let _ = futures_poll_once(save).await;
+        }
+
+        // Give the background write time to notice it was abandoned
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(std::fs::read_dir(dir.path()).unwrap().all(|entry| {
+            !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".partial")
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_save_image_dropped_after_publishing_keeps_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("output.png");
+        std::fs::write(&output_path, [0]).unwrap();
+        let service = ImageService::new().unwrap();
+
+        {
+            let mut save =
+                std::pin::pin!(service.save_image(output_path.to_str().unwrap(), vec![1, 2, 3]));
+            assert!(futures_poll_once(save.as_mut()).await.is_none());
+            // Let the rename land, then cancel before the save is polled again
+            for _ in 0..100 {
+                if std::fs::read(&output_path).unwrap() != [0] {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        }
+
+        assert_eq!(std::fs::read(&output_path).unwrap(), vec![1, 2, 3]);
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbImage::new(width, height);
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, ImageFormat::Png).unwrap();
+        encoded.into_inner()
+    }
+
+    #[test]
+    fn test_downscale_image_fits_within_max_dimension() {
+        let downscaled = downscale_image(&png(400, 200), 100).unwrap().unwrap();
+
+        let image = image::load_from_memory(&downscaled).unwrap();
+        assert_eq!((image.width(), image.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_image_dimensions_reads_header() {
+        assert_eq!(image_dimensions(&png(32, 16)), Some((32, 16)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_downscale_image_keeps_small_images() {
+        assert!(downscale_image(&png(64, 64), 100).unwrap().is_none());
+        assert!(matches!(
+            downscale_image(b"not an image", 100),
+            Err(McpError::ContentTypeError(_))
+        ));
+    }
+
+    async fn futures_poll_once<F: std::future::Future>(future: F) -> Option<F::Output> {
+        let mut future = std::pin::pin!(future);
+        std::future::poll_fn(|cx| match future.as_mut().poll(cx) {
+            std::task::Poll::Ready(output) => std::task::Poll::Ready(Some(output)),
+            std::task::Poll::Pending => std::task::Poll::Ready(None),
+        })
+        .await
+    }
+}
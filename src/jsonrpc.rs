@@ -1,11 +1,31 @@
+use crate::completion::{
+    complete_image_path, complete_output_path, complete_roots, completion_result, is_image_argument,
+};
+use crate::config::ServerConfig;
 use crate::error::McpError;
-use crate::gemini_client::{
-    AnalyzeImageInput, ComposeImagesInput, EditImageInput, GeminiClient, GenerateImageInput,
-    InpaintImageInput, RefineImageInput, StyleTransferInput,
+use crate::gemini_client::{GeminiClient, GeneratedImage};
+use crate::gemini_tools::gemini_tools;
+use crate::image_service::downscale_image;
+use crate::logging::{ClientLogger, LogLevel};
+use crate::peer::Peer;
+use crate::progress::ProgressReporter;
+use crate::prompts::PromptLibrary;
+use crate::resources::{
+    GENERATION_URI_TEMPLATE, GenerationRegistry, list_resources, read_resource,
 };
+use crate::roots::{FileAccess, roots_from_list};
+use crate::tools::{Tool, ToolContext, ToolError, ToolRegistry, ToolSelection, tool_error_result};
+use base64::{Engine as _, engine::general_purpose};
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
-use tracing::{error, info};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{mpsc, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, Span, debug, error, info, info_span, warn};
 
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcRequest {
@@ -27,6 +47,19 @@ pub struct JsonRpcResponse {
 }
 
 impl JsonRpcResponse {
+    /// Serializes the response, falling back to an internal error if that fails.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|e| {
+            error!("Failed to serialize response: {}", e);
+            let fallback_error = JsonRpcResponse::error(
+                self.id.clone(),
+                -32603,
+                "Internal error - serialization failed".to_string(),
+            );
+            serde_json::to_string(&fallback_error).unwrap_or_default()
+        })
+    }
+
     pub fn error(id: Option<Value>, code: i32, message: String) -> Self {
         Self {
             jsonrpc: "2.0".to_string(),
@@ -43,31 +76,522 @@ pub struct JsonRpcError {
     pub message: String,
 }
 
+/// One frame received from a transport: a single message or a JSON-RPC batch.
+#[derive(Debug)]
+pub enum JsonRpcPayload {
+    Single(JsonRpcRequest),
+    /// The client's answer to a request the server sent
+    Response(Value),
+    /// Batch entries stay raw so a malformed entry only fails that entry
+    Batch(Vec<Value>),
+}
+
+impl JsonRpcPayload {
+    pub fn parse(bytes: &[u8]) -> Result<Self, JsonRpcResponse> {
+        let value: Value = serde_json::from_slice(bytes).map_err(|parse_error| {
+            error!("Failed to parse JSON-RPC message: {}", parse_error);
+            JsonRpcResponse::error(None, -32700, format!("Parse error: {}", parse_error))
+        })?;
+
+        match value {
+            Value::Array(entries) => Ok(JsonRpcPayload::Batch(entries)),
+            value if is_response(&value) => Ok(JsonRpcPayload::Response(value)),
+            value => parse_request(value).map(JsonRpcPayload::Single),
+        }
+    }
+
+    /// True when nothing in the payload expects a response.
+    pub fn is_notification_only(&self) -> bool {
+        match self {
+            JsonRpcPayload::Single(request) => request.id.is_none(),
+            JsonRpcPayload::Response(_) => true,
+            JsonRpcPayload::Batch(entries) => {
+                !entries.is_empty()
+                    && entries.iter().all(|entry| {
+                        is_response(entry) || entry.get("id").is_none_or(Value::is_null)
+                    })
+            }
+        }
+    }
+
+    pub fn is_initialize(&self) -> bool {
+        matches!(self, JsonRpcPayload::Single(request) if request.method == "initialize")
+    }
+}
+
+/// The response to a `JsonRpcPayload`; batches answer with an array.
+#[derive(Debug)]
+pub enum JsonRpcReply {
+    Single(JsonRpcResponse),
+    Batch(Vec<JsonRpcResponse>),
+}
+
+impl JsonRpcReply {
+    pub fn to_json(&self) -> String {
+        match self {
+            JsonRpcReply::Single(response) => response.to_json(),
+            JsonRpcReply::Batch(responses) => {
+                let entries: Vec<String> = responses.iter().map(JsonRpcResponse::to_json).collect();
+                format!("[{}]", entries.join(","))
+            }
+        }
+    }
+}
+
+/// Responses carry a result or an error instead of a method.
+fn is_response(value: &Value) -> bool {
+    value.get("method").is_none() && (value.get("result").is_some() || value.get("error").is_some())
+}
+
+fn parse_request(value: Value) -> Result<JsonRpcRequest, JsonRpcResponse> {
+    let id = value.get("id").cloned().filter(|id| !id.is_null());
+    serde_json::from_value(value).map_err(|e| {
+        error!("Invalid JSON-RPC request: {}", e);
+        JsonRpcResponse::error(id, -32600, format!("Invalid Request: {}", e))
+    })
+}
+
+/// Protocol revisions this server speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const SERVER_INSTRUCTIONS: &str = "Image tools backed by Google Gemini. \
+Use analyze_image to describe an image or answer questions about it; it is read-only and returns text. \
+Use generate_image to create a new image from a text prompt, and edit_image, inpaint_image, style_transfer, \
+compose_images or refine_image to derive a new image from existing ones. \
+Image inputs may be HTTPS URLs or local file paths (jpg, jpeg, png, gif, webp, bmp, tiff; max 20MB each). \
+Generator tools write the result to output_path, which must use an image extension and whose directory must already exist. \
+If output_path already exists, overwrite_policy decides whether to ask the user (default), overwrite, rename to a free name or fail. \
+Every image tool accepts an optional model, e.g. gemini-2.5-pro for hard analyses; leave it out to use the server default. \
+An optional generation_config (temperature, top_p, top_k, seed, max_output_tokens) controls sampling; set seed for reproducible output. \
+Use list_models to find the available models, e.g. with output_modality \"image\" for the ones that generate images. \
+Generation can take tens of seconds; send _meta.progressToken to receive progress notifications.";
+
+/// What the client told us about itself during `initialize`.
+#[derive(Debug, Default)]
+struct ClientInfo {
+    protocol_version: Option<String>,
+    capabilities: Value,
+}
+
+tokio::task_local! {
+    /// Peer for messages tied to the request being handled, e.g. progress streamed
+    /// back on the HTTP response of that request instead of the session stream.
+    static REQUEST_PEER: Peer;
+}
+
 pub struct JsonRpcHandler {
-    gemini_client: Option<GeminiClient>,
+    config: Arc<ServerConfig>,
+    tools: Arc<ToolRegistry>,
+    /// Which tools are offered; replaced when the config file changes
+    tool_selection: watch::Receiver<ToolSelection>,
+    prompts: Arc<PromptLibrary>,
+    initialized: AtomicBool,
+    client: RwLock<ClientInfo>,
+    /// Cancellation tokens of in-flight requests, keyed by their serialized JSON-RPC id
+    in_flight: Mutex<HashMap<String, CancellationToken>>,
+    logger: ClientLogger,
+    /// Images written by the generator tools in this session, exposed as resources
+    generations: GenerationRegistry,
+    /// Client roots, fetched on first file access and dropped when they change
+    roots: Mutex<ClientRoots>,
+    peer: Peer,
+}
+
+/// The client's roots as far as this session knows them.
+#[derive(Debug, Default)]
+struct ClientRoots {
+    /// Bumped by every `notifications/roots/list_changed`
+    generation: u64,
+    state: RootsState,
+}
+
+#[derive(Debug, Clone, Default)]
+enum RootsState {
+    /// Not asked yet, or changed since
+    #[default]
+    Unknown,
+    Listed(Vec<PathBuf>),
+    /// `roots/list` failed; the fallback directories apply until the roots change
+    Unavailable,
+}
+
+/// Removes a request from the in-flight registry once it completes or is dropped.
+struct InFlightGuard<'a> {
+    registry: &'a Mutex<HashMap<String, CancellationToken>>,
+    key: String,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().remove(&self.key);
+    }
 }
 
 impl JsonRpcHandler {
     pub fn new(api_key: Option<String>) -> Self {
-        let gemini_client = match api_key {
-            Some(key) if !key.trim().is_empty() => match GeminiClient::new(key) {
-                Ok(client) => Some(client),
+        Self::with_config(ServerConfig {
+            api_key,
+            ..ServerConfig::default()
+        })
+    }
+
+    pub fn with_config(config: ServerConfig) -> Self {
+        let gemini_client = match (&config.vertex, &config.api_key) {
+            (Some(vertex), _) => Some(GeminiClient::vertex(vertex.clone())),
+            (None, Some(key)) if !key.trim().is_empty() => Some(GeminiClient::new(key.clone())),
+            _ => None,
+        }
+        .map(|client| {
+            let client = client?
+                .with_default_models(&config.analysis_model, &config.image_model)
+                .with_models_cache_ttl(config.models_cache_ttl)
+                .with_max_concurrent_requests(config.max_concurrent_requests)
+                .with_generation_defaults(config.generation_defaults);
+            match &config.api_base_url {
+                Some(url) => client.with_base_url(url),
+                None => Ok(client),
+            }
+        })
+        .and_then(|client| match client {
+            Ok(client) => Some(Arc::new(client)),
+            Err(e) => {
+                error!("Failed to create Gemini client: {}", e);
+                None
+            }
+        });
+
+        let prompts = match &config.prompts_dir {
+            Some(dir) => match PromptLibrary::load(dir) {
+                Ok(prompts) => prompts,
                 Err(e) => {
-                    error!("Failed to create Gemini client: {}", e);
-                    None
+                    error!("Failed to load prompts, using built-in prompts only: {}", e);
+                    PromptLibrary::default()
                 }
             },
-            _ => None,
+            None => PromptLibrary::default(),
+        };
+
+        let mut tools = ToolRegistry::new();
+        for tool in gemini_tools(gemini_client) {
+            tools.register(tool);
+        }
+
+        // Without a config file the selection never changes
+        let (_, tool_selection) = watch::channel(config.tools.clone());
+
+        Self {
+            config: Arc::new(config),
+            tools: Arc::new(tools),
+            tool_selection,
+            prompts: Arc::new(prompts),
+            initialized: AtomicBool::new(false),
+            client: RwLock::new(ClientInfo::default()),
+            in_flight: Mutex::new(HashMap::new()),
+            logger: ClientLogger::new(),
+            generations: GenerationRegistry::default(),
+            roots: Mutex::default(),
+            peer: Peer::disconnected(),
+        }
+    }
+
+    /// Creates a handler for a new client session that shares the tools and
+    /// concurrency limit with this one but starts with fresh session state.
+    pub fn new_session(&self) -> Self {
+        Self {
+            config: Arc::clone(&self.config),
+            tools: Arc::clone(&self.tools),
+            tool_selection: self.tool_selection.clone(),
+            prompts: Arc::clone(&self.prompts),
+            initialized: AtomicBool::new(false),
+            client: RwLock::new(ClientInfo::default()),
+            in_flight: Mutex::new(HashMap::new()),
+            logger: ClientLogger::new(),
+            generations: GenerationRegistry::default(),
+            roots: Mutex::default(),
+            peer: Peer::disconnected(),
+        }
+    }
+
+    /// Adds a tool next to the built-in ones, replacing a tool with the same name.
+    /// Register tools before serving; sessions share the tools of the handler
+    /// they were created from.
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        Arc::make_mut(&mut self.tools).register(Arc::new(tool));
+        self
+    }
+
+    /// Follows a tool selection that changes at runtime, e.g. from a reloaded config file.
+    pub fn with_tool_selection(mut self, tool_selection: watch::Receiver<ToolSelection>) -> Self {
+        self.tool_selection = tool_selection;
+        self
+    }
+
+    /// Names in the current tool selection that match no registered tool.
+    pub fn unknown_selected_tools(&self) -> Vec<String> {
+        self.tool_selection.borrow().unknown_tools(&self.tools)
+    }
+
+    /// Sends `notifications/tools/list_changed` each time the tool selection
+    /// changes, until the client disconnects.
+    pub async fn forward_tool_list_changes(&self) {
+        let mut selection = self.tool_selection.clone();
+        loop {
+            tokio::select! {
+                changed = selection.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    info!("Tool selection changed; notifying client");
+                    self.peer.notify("notifications/tools/list_changed", json!({}));
+                }
+                _ = self.peer.closed() => return,
+            }
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Connects the handler to the client so it can send notifications.
+    pub fn with_peer(mut self, peer: Peer) -> Self {
+        self.logger = self.logger.with_peer(peer.clone());
+        self.peer = peer;
+        self
+    }
+
+    /// Handles a single message or a batch. Batch entries are dispatched
+    /// concurrently and notifications are left out of the batch response.
+    pub async fn handle_payload(&self, payload: JsonRpcPayload) -> Option<JsonRpcReply> {
+        match payload {
+            JsonRpcPayload::Single(request) => {
+                self.handle_message(request).await.map(JsonRpcReply::Single)
+            }
+            JsonRpcPayload::Response(response) => {
+                self.peer.handle_response(response);
+                None
+            }
+            JsonRpcPayload::Batch(entries) if entries.is_empty() => Some(JsonRpcReply::Single(
+                JsonRpcResponse::error(None, -32600, "Invalid Request: empty batch".to_string()),
+            )),
+            JsonRpcPayload::Batch(entries) => {
+                let responses = join_all(entries.into_iter().map(|entry| async move {
+                    if is_response(&entry) {
+                        self.peer.handle_response(entry);
+                        return None;
+                    }
+                    match parse_request(entry) {
+                        Ok(request) => self.handle_message(request).await,
+                        Err(error_response) => Some(error_response),
+                    }
+                }))
+                .await;
+
+                let responses: Vec<JsonRpcResponse> = responses.into_iter().flatten().collect();
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(JsonRpcReply::Batch(responses))
+                }
+            }
+        }
+    }
+
+    /// Like `handle_payload`, but messages tied to these requests go to `sender`.
+    pub async fn handle_payload_with_sender(
+        &self,
+        payload: JsonRpcPayload,
+        sender: mpsc::UnboundedSender<String>,
+    ) -> Option<JsonRpcReply> {
+        let peer = self.peer.with_sender(sender);
+        REQUEST_PEER.scope(peer, self.handle_payload(payload)).await
+    }
+
+    fn request_peer(&self) -> Peer {
+        REQUEST_PEER
+            .try_with(Peer::clone)
+            .unwrap_or_else(|_| self.peer.clone())
+    }
+
+    fn progress_reporter(&self, params: &Value) -> ProgressReporter {
+        match params.pointer("/_meta/progressToken") {
+            Some(token) if token.is_string() || token.is_i64() || token.is_u64() => {
+                ProgressReporter::new(token.clone(), self.request_peer())
+            }
+            _ => ProgressReporter::none(),
+        }
+    }
+
+    /// Builds the result of a generator tool. The image itself is attached as an
+    /// image content block when the call or the server default asks for it.
+    pub(crate) async fn generated_image_result(
+        &self,
+        text: String,
+        image: GeneratedImage,
+        include_image: Option<bool>,
+    ) -> Value {
+        let output = image.output();
+        self.generations.record(&image);
+        self.peer
+            .notify("notifications/resources/list_changed", json!({}));
+
+        let mut content = vec![json!({ "type": "text", "text": text })];
+        if include_image.unwrap_or(self.config.include_image)
+            && let Some(block) = self.image_content_block(image).await
+        {
+            content.push(block);
+        }
+
+        json!({
+            "content": content,
+            "structuredContent": output
+        })
+    }
+
+    async fn image_content_block(&self, image: GeneratedImage) -> Option<Value> {
+        let (mime_type, data) = match self.config.image_max_dimension {
+            Some(max_dimension) => {
+                let file_path = image.file_path.clone();
+                let downscaled = tokio::task::spawn_blocking(move || {
+                    downscale_image(&image.data, max_dimension).map(|downscaled| match downscaled {
+                        Some(png) => ("image/png".to_string(), png),
+                        None => (image.mime_type, image.data),
+                    })
+                })
+                .await;
+                match downscaled {
+                    Ok(Ok(downscaled)) => downscaled,
+                    Ok(Err(e)) => {
+                        warn!("Not attaching image '{}': {}", file_path, e);
+                        return None;
+                    }
+                    Err(e) => {
+                        warn!("Not attaching image '{}': {}", file_path, e);
+                        return None;
+                    }
+                }
+            }
+            None => (image.mime_type, image.data),
+        };
+
+        Some(json!({
+            "type": "image",
+            "data": general_purpose::STANDARD.encode(&data),
+            "mimeType": mime_type
+        }))
+    }
+
+    /// Handles any incoming message. Messages without an `id` are notifications
+    /// and never produce a response.
+    pub async fn handle_message(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        // Log events emitted while handling the message go to the same peer as its response
+        self.logger
+            .with_peer(self.request_peer())
+            .scope(self.dispatch_message(request))
+            .await
+    }
+
+    async fn dispatch_message(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        let Some(id) = request.id.clone() else {
+            self.handle_notification(request).await;
+            return None;
+        };
+
+        let span = request_span(&id, &request);
+        self.handle_cancellable_request(id, request)
+            .instrument(span)
+            .await
+    }
+
+    async fn handle_cancellable_request(
+        &self,
+        id: Value,
+        request: JsonRpcRequest,
+    ) -> Option<JsonRpcResponse> {
+        // The initialize request must never be cancelled
+        if request.method == "initialize" {
+            return Some(self.handle_request(request).await);
+        }
+
+        let key = request_key(&id);
+        let token = CancellationToken::new();
+        self.in_flight
+            .lock()
+            .unwrap()
+            .insert(key.clone(), token.clone());
+        let _guard = InFlightGuard {
+            registry: &self.in_flight,
+            key,
         };
 
-        Self { gemini_client }
+        // Dropping the handler future aborts any Gemini call or image download it is
+        // awaiting; cancelled requests get no response
+        tokio::select! {
+            response = self.handle_request(request) => Some(response),
+            _ = token.cancelled() => {
+                info!("Request {} was cancelled before completion", id);
+                None
+            }
+        }
+    }
+
+    async fn handle_notification(&self, notification: JsonRpcRequest) {
+        match notification.method.as_str() {
+            "notifications/initialized" => {
+                self.initialized.store(true, Ordering::SeqCst);
+                info!("Client completed initialization");
+            }
+            "notifications/cancelled" => {
+                let params = notification.params.unwrap_or(Value::Null);
+                let request_id = params.get("requestId").cloned().unwrap_or(Value::Null);
+                let reason = params
+                    .get("reason")
+                    .and_then(|r| r.as_str())
+                    .unwrap_or("no reason given");
+                info!("Client cancelled request {}: {}", request_id, reason);
+                if let Some(token) = self
+                    .in_flight
+                    .lock()
+                    .unwrap()
+                    .get(&request_key(&request_id))
+                {
+                    token.cancel();
+                }
+            }
+            "notifications/roots/list_changed" => {
+                info!("Client roots changed");
+                let mut roots = self.roots.lock().unwrap();
+                roots.generation += 1;
+                roots.state = RootsState::Unknown;
+            }
+            method if method.starts_with("notifications/") => {
+                debug!("Ignoring unsupported notification: {}", method);
+            }
+            method => {
+                warn!("Ignoring request without id: {}", method);
+            }
+        }
     }
 
     pub async fn handle_request(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        if request.method != "initialize" && !self.initialized.load(Ordering::SeqCst) {
+            debug!(
+                "Received '{}' before notifications/initialized",
+                request.method
+            );
+        }
+
         match request.method.as_str() {
             "initialize" => self.handle_initialize(request).await,
             "tools/list" => self.handle_tools_list(request).await,
             "tools/call" => self.handle_tools_call(request).await,
+            "logging/setLevel" => self.handle_set_log_level(request),
+            "completion/complete" => self.handle_completion_complete(request).await,
+            "prompts/list" => self.handle_prompts_list(request),
+            "prompts/get" => self.handle_prompts_get(request),
+            "resources/list" => self.handle_resources_list(request).await,
+            "resources/templates/list" => self.handle_resource_templates_list(request),
+            "resources/read" => self.handle_resources_read(request).await,
             _ => JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 id: request.id,
@@ -81,15 +605,41 @@ impl JsonRpcHandler {
     }
 
     async fn handle_initialize(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params = request.params.unwrap_or(Value::Null);
+        let requested_version = params.get("protocolVersion").and_then(|v| v.as_str());
+        let protocol_version = negotiate_protocol_version(requested_version);
+
+        if let Some(client_info) = params.get("clientInfo") {
+            info!(
+                "Initializing session for client {} {} (requested protocol {}, using {})",
+                client_info
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown"),
+                client_info
+                    .get("version")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(""),
+                requested_version.unwrap_or("none"),
+                protocol_version
+            );
+        }
+
+        {
+            let mut client = self.client.write().unwrap();
+            client.protocol_version = Some(protocol_version.to_string());
+            client.capabilities = params.get("capabilities").cloned().unwrap_or(json!({}));
+        }
+
         let result = json!({
-            "protocolVersion": "2024-11-05",
-            "capabilities": {
-                "tools": {}
-            },
+            "protocolVersion": protocol_version,
+            "capabilities": self.server_capabilities(),
             "serverInfo": {
-                "name": "gemini-image-mcp",
-                "version": "1.1.0"
-            }
+                "name": env!("CARGO_PKG_NAME"),
+                "title": "Gemini Image MCP",
+                "version": env!("CARGO_PKG_VERSION")
+            },
+            "instructions": SERVER_INSTRUCTIONS
         });
         JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
@@ -99,329 +649,428 @@ impl JsonRpcHandler {
         }
     }
 
-    async fn handle_tools_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let tools = json!([
-            {
-                "name": "analyze_image",
-                "description": "Analyze an image using Google's Gemini API. Supports both URLs (http/https) and local file paths.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(AnalyzeImageInput)).unwrap()
-            },
-            {
-                "name": "generate_image",
-                "description": "Generate an image using Google's Gemini API with optional system prompt and required user prompt.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(GenerateImageInput)).unwrap()
-            },
-            {
-                "name": "edit_image",
-                "description": "Edit an existing image using Google's Gemini API by providing both an input image and a text prompt describing the desired changes.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(EditImageInput)).unwrap()
-            },
-            {
-                "name": "inpaint_image",
-                "description": "Inpaint/modify specific regions of an image using semantic masking. Supports focusing on specific elements or regions.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(InpaintImageInput)).unwrap()
-            },
-            {
-                "name": "style_transfer",
-                "description": "Transfer the artistic style from one image to another using Google's Gemini API.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(StyleTransferInput)).unwrap()
-            },
-            {
-                "name": "compose_images",
-                "description": "Compose multiple images into a single new image using Google's Gemini API.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(ComposeImagesInput)).unwrap()
-            },
-            {
-                "name": "refine_image",
-                "description": "Iteratively refine an image with conversation history for progressive improvement using Google's Gemini API.",
-                "inputSchema": serde_json::to_value(schemars::schema_for!(RefineImageInput)).unwrap()
-            }
-        ]);
-        let result = json!({ "tools": tools });
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id,
-            result: Some(result),
-            error: None,
+    /// Capabilities advertised in `initialize`, limited to what this server can
+    /// do in its current configuration.
+    fn server_capabilities(&self) -> Value {
+        // The sender is dropped unless a config file is watched
+        let tools_can_change = self.tool_selection.has_changed().is_ok();
+        // Resources are this session's generations and the images in `--output-dir`
+        let has_resources = tools_can_change
+            || !self.config.output_dirs.is_empty()
+            || self
+                .tools
+                .offers_writing_tools(&self.tool_selection.borrow());
+        let has_prompts = !self.prompts.list().is_empty();
+
+        let mut capabilities = json!({
+            "logging": {},
+            "tools": { "listChanged": tools_can_change }
+        });
+        if has_prompts {
+            capabilities["prompts"] = json!({});
+        }
+        if has_resources {
+            capabilities["resources"] = json!({ "listChanged": true });
+        }
+        if has_prompts || has_resources {
+            capabilities["completions"] = json!({});
         }
+        capabilities
     }
 
-    async fn handle_tools_call(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        if let Some(params) = request.params
-            && let Ok(tool_call) = serde_json::from_value::<Value>(params)
-        {
-            if let Some(name) = tool_call.get("name").and_then(|v| v.as_str()) {
-                if name == "analyze_image" {
-                    return self.handle_analyze_image(request.id, tool_call).await;
-                } else if name == "generate_image" {
-                    return self.handle_generate_image(request.id, tool_call).await;
-                } else if name == "edit_image" {
-                    return self.handle_edit_image(request.id, tool_call).await;
-                } else if name == "inpaint_image" {
-                    return self.handle_inpaint_image(request.id, tool_call).await;
-                } else if name == "style_transfer" {
-                    return self.handle_style_transfer(request.id, tool_call).await;
-                } else if name == "compose_images" {
-                    return self.handle_compose_images(request.id, tool_call).await;
-                } else if name == "refine_image" {
-                    return self.handle_refine_image(request.id, tool_call).await;
-                } else {
-                    return JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id: request.id,
-                        result: None,
-                        error: Some(JsonRpcError {
-                            code: -1,
-                            message: format!("Unknown tool: {}", name),
-                        }),
-                    };
-                }
-            } else {
-                return JsonRpcResponse {
+    fn handle_set_log_level(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let level = request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("level"))
+            .map(|level| serde_json::from_value::<LogLevel>(level.clone()));
+
+        match level {
+            Some(Ok(level)) => {
+                self.logger.set_level(level);
+                JsonRpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: request.id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -1,
-                        message: "Missing tool name".to_string(),
-                    }),
-                };
+                    result: Some(json!({})),
+                    error: None,
+                }
             }
+            Some(Err(e)) => JsonRpcResponse::error(
+                request.id,
+                -32602,
+                format!("Invalid params: unknown log level: {}", e),
+            ),
+            None => JsonRpcResponse::error(
+                request.id,
+                -32602,
+                "Invalid params: missing level".to_string(),
+            ),
         }
+    }
+
+    async fn handle_completion_complete(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params = request.params.unwrap_or(Value::Null);
+        let reference = &params["ref"];
+        let (Some(argument), Some(value)) = (
+            params.pointer("/argument/name").and_then(|v| v.as_str()),
+            params.pointer("/argument/value").and_then(|v| v.as_str()),
+        ) else {
+            return JsonRpcResponse::error(
+                request.id,
+                -32602,
+                "Invalid params: missing argument name or value".to_string(),
+            );
+        };
+
+        let values = match reference["type"].as_str() {
+            Some("ref/prompt") => {
+                let prompt = reference["name"].as_str().unwrap_or_default();
+                let bases = self.completion_bases().await;
+                if is_image_argument(argument) {
+                    let files = self.file_access().await;
+                    let mut values = complete_image_path(value, &bases, &files).await;
+                    values.extend(complete_roots(value, files.roots()));
+                    values
+                } else if argument == "output_path" {
+                    let files = self.file_access().await;
+                    let mut values =
+                        complete_output_path(value, &self.config.output_dirs, &bases, &files).await;
+                    values.extend(complete_roots(value, files.roots()));
+                    values
+                } else {
+                    self.prompts.presets(prompt, argument, value)
+                }
+            }
+            Some("ref/resource") if reference["uri"] == GENERATION_URI_TEMPLATE => self
+                .generations
+                .ids()
+                .into_iter()
+                .filter(|id| id.starts_with(value))
+                .collect(),
+            Some("ref/resource") => vec![],
+            _ => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    -32602,
+                    "Invalid params: unsupported completion reference".to_string(),
+                );
+            }
+        };
 
         JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
             id: request.id,
-            result: None,
-            error: Some(JsonRpcError {
-                code: -1,
-                message: "Invalid params".to_string(),
-            }),
+            result: Some(completion_result(values)),
+            error: None,
         }
     }
 
-    async fn handle_analyze_image(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        // Check if client is available
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
-            }
-        };
+    /// Directories relative paths are completed against: the client's roots
+    /// when it lists them, otherwise the working directory.
+    async fn completion_bases(&self) -> Vec<PathBuf> {
+        match self.client_roots().await {
+            Some(listed) => listed,
+            None => std::env::current_dir().into_iter().collect(),
+        }
+    }
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<AnalyzeImageInput>(arguments.clone()) {
-                Ok(input) => match client.analyze_image(&input).await {
-                    Ok(analysis) => {
-                        info!("Successfully analyzed image: {}", input.image_source);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": analysis
-                                }
-                            ]
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to analyze image '{}': {}", input.image_source, e);
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for analyze_image: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
-                }
-            }
-        } else {
-            JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
-            }
+    fn client_supports(&self, capability: &str) -> bool {
+        self.client
+            .read()
+            .unwrap()
+            .capabilities
+            .get(capability)
+            .is_some()
+    }
+
+    /// Where tools may read and write, and how existing output files are handled.
+    /// Overwrites are confirmed through the requesting peer when the client
+    /// supports elicitation.
+    pub(crate) async fn file_access(&self) -> FileAccess {
+        let elicitor = self
+            .client_supports("elicitation")
+            .then(|| self.request_peer());
+        FileAccess::new(&self.allowed_roots().await)
+            .await
+            .with_overwrite(self.config.overwrite_policy, elicitor)
+    }
+
+    /// The client's roots when it supports them, otherwise `--allowed-dir` or
+    /// the working directory.
+    async fn allowed_roots(&self) -> Vec<PathBuf> {
+        match self.client_roots().await {
+            Some(listed) => listed,
+            None => self.fallback_roots(),
         }
     }
 
-    async fn handle_generate_image(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
+    /// The roots the client lists, or `None` when it doesn't support roots or
+    /// listing them failed.
+    /// A failed `roots/list` is not retried until the client says its roots changed.
+    async fn client_roots(&self) -> Option<Vec<PathBuf>> {
+        if !self.client_supports("roots") {
+            return None;
+        }
+        let generation = {
+            let roots = self.roots.lock().unwrap();
+            match &roots.state {
+                RootsState::Listed(listed) => return Some(listed.clone()),
+                RootsState::Unavailable => return None,
+                RootsState::Unknown => roots.generation,
             }
         };
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<GenerateImageInput>(arguments.clone()) {
-                Ok(input) => match client.generate_image(&input).await {
-                    Ok(file_path) => {
-                        info!("Successfully generated and saved image to: {}", file_path);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Image successfully generated and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to generate image with prompt '{}': {}",
-                            input.user_prompt, e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for generate_image: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
-                }
+        // Not locked while waiting: the client may take until the request timeout
+        let state = match self.request_peer().request("roots/list", json!({})).await {
+            Ok(result) => {
+                let listed = roots_from_list(&result);
+                debug!("Client roots: {:?}", listed);
+                RootsState::Listed(listed)
             }
-        } else {
-            JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
+            Err(e) => {
+                warn!(
+                    "Failed to list client roots, using the allowed directories until they change: {}",
+                    e
+                );
+                RootsState::Unavailable
             }
+        };
+        let mut roots = self.roots.lock().unwrap();
+        // A listing requested before the roots changed is used once but not kept
+        if roots.generation == generation {
+            roots.state = state.clone();
+        }
+        match state {
+            RootsState::Listed(listed) => Some(listed),
+            _ => None,
         }
     }
 
-    async fn handle_edit_image(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
-            }
-        };
-
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<EditImageInput>(arguments.clone()) {
-                Ok(input) => match client.edit_image(&input).await {
-                    Ok(file_path) => {
-                        info!("Successfully edited and saved image to: {}", file_path);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Image successfully edited and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to edit image '{}' with prompt '{}': {}",
-                            input.image_source, input.user_prompt, e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for edit_image: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
-                }
-            }
+    /// `--allowed-dir`, or the working directory when none is given.
+    fn fallback_roots(&self) -> Vec<PathBuf> {
+        if self.config.allowed_dirs.is_empty() {
+            std::env::current_dir().into_iter().collect()
         } else {
-            JsonRpcResponse {
+            self.config.allowed_dirs.clone()
+        }
+    }
+
+    fn handle_prompts_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(json!({ "prompts": self.prompts.list() })),
+            error: None,
+        }
+    }
+
+    fn handle_prompts_get(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params = request.params.unwrap_or(Value::Null);
+        let Some(name) = params.get("name").and_then(|name| name.as_str()) else {
+            return JsonRpcResponse::error(
+                request.id,
+                -32602,
+                "Invalid params: missing prompt name".to_string(),
+            );
+        };
+        let arguments = params
+            .get("arguments")
+            .and_then(|arguments| arguments.as_object())
+            .cloned()
+            .unwrap_or_default();
+
+        match self.prompts.get(name, &arguments) {
+            Some(Ok(result)) => JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
-                id,
+                id: request.id,
+                result: Some(result),
+                error: None,
+            },
+            Some(Err(e)) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
                 result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
-            }
+                error: Some(convert_mcp_error_to_jsonrpc(e)),
+            },
+            None => JsonRpcResponse::error(
+                request.id,
+                -32602,
+                format!("Invalid params: unknown prompt: {}", name),
+            ),
         }
     }
-}
 
-fn convert_mcp_error_to_jsonrpc(error: McpError) -> JsonRpcError {
-    match error {
-        McpError::InvalidInput(msg) => JsonRpcError {
-            code: -32602,
-            message: format!("Invalid params: {}", msg),
+    async fn handle_resources_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let resources = list_resources(&self.generations, &self.config.output_dirs).await;
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(json!({ "resources": resources })),
+            error: None,
+        }
+    }
+
+    fn handle_resource_templates_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let result = json!({
+            "resourceTemplates": [
+                {
+                    "uriTemplate": GENERATION_URI_TEMPLATE,
+                    "name": "generation",
+                    "title": "Generated image",
+                    "description": "An image written by one of the generator tools in this session"
+                }
+            ]
+        });
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    async fn handle_resources_read(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let Some(uri) = request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("uri"))
+            .and_then(|uri| uri.as_str())
+        else {
+            return JsonRpcResponse::error(
+                request.id,
+                -32602,
+                "Invalid params: missing uri".to_string(),
+            );
+        };
+
+        match read_resource(&self.generations, &self.config.output_dirs, uri).await {
+            Ok(Some(contents)) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(json!({ "contents": [contents] })),
+                error: None,
+            },
+            Ok(None) => {
+                JsonRpcResponse::error(request.id, -32002, format!("Resource not found: {}", uri))
+            }
+            Err(e) => {
+                error!("Failed to read resource '{}': {}", uri, e);
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result: None,
+                    error: Some(convert_mcp_error_to_jsonrpc(e)),
+                }
+            }
+        }
+    }
+
+    async fn handle_tools_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id: request.id,
+            result: Some(json!({ "tools": self.tools.list(&self.tool_selection.borrow()) })),
+            error: None,
+        }
+    }
+
+    async fn handle_tools_call(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params = request.params.unwrap_or(Value::Null);
+        let Some(name) = params.get("name").and_then(|v| v.as_str()) else {
+            return JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: -1,
+                    message: "Missing tool name".to_string(),
+                }),
+            };
+        };
+        let Some(tool) = self.tools.get(name) else {
+            return JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: -1,
+                    message: format!("Unknown tool: {}", name),
+                }),
+            };
+        };
+
+        if !self.tool_selection.borrow().allows(tool.as_ref()) {
+            return JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(JsonRpcError {
+                    code: -1,
+                    message: format!("Tool disabled on this server: {}", name),
+                }),
+            };
+        }
+
+        let context = ToolContext::new(self, self.progress_reporter(&params));
+        let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+        match tool.call(arguments, &context).await {
+            Ok(result) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(result),
+                error: None,
+            },
+            Err(ToolError::Failed(e)) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: Some(tool_error_result(&e)),
+                error: None,
+            },
+            Err(ToolError::InvalidRequest(e)) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: request.id,
+                result: None,
+                error: Some(convert_mcp_error_to_jsonrpc(e)),
+            },
+        }
+    }
+}
+
+/// Picks the client's protocol version when we support it, otherwise our newest one.
+fn negotiate_protocol_version(requested: Option<&str>) -> &'static str {
+    requested
+        .and_then(|requested| {
+            SUPPORTED_PROTOCOL_VERSIONS
+                .iter()
+                .find(|&&supported| supported == requested)
+        })
+        .copied()
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
+/// Span that ties log lines to a JSON-RPC request and, for `tools/call`, the tool.
+fn request_span(id: &Value, request: &JsonRpcRequest) -> Span {
+    let tool = if request.method == "tools/call" {
+        request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("name"))
+            .and_then(|name| name.as_str())
+    } else {
+        None
+    };
+    info_span!("request", id = %id, method = %request.method, tool)
+}
+
+fn request_key(id: &Value) -> String {
+    id.to_string()
+}
+
+fn convert_mcp_error_to_jsonrpc(error: McpError) -> JsonRpcError {
+    match error {
+        McpError::InvalidInput(msg) => JsonRpcError {
+            code: -32602,
+            message: format!("Invalid params: {}", msg),
         },
         McpError::AuthenticationError(msg) => JsonRpcError {
             code: -32001,
@@ -480,9 +1129,106 @@ mod tests {
         assert!(response.result.is_some());
 
         let result = response.result.unwrap();
-        assert_eq!(result["protocolVersion"], "2024-11-05");
+        assert_eq!(result["protocolVersion"], "2025-06-18");
         assert_eq!(result["serverInfo"]["name"], "gemini-image-mcp");
-        assert_eq!(result["serverInfo"]["version"], "1.1.0");
+        assert_eq!(result["serverInfo"]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(result["capabilities"]["tools"].is_object());
+        assert!(
+            result["instructions"]
+                .as_str()
+                .unwrap()
+                .contains("analyze_image")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initialize_negotiates_protocol_version() {
+        for (requested, expected) in [
+            ("2025-06-18", "2025-06-18"),
+            ("2025-03-26", "2025-03-26"),
+            ("2024-11-05", "2024-11-05"),
+            ("1999-01-01", "2025-06-18"),
+        ] {
+            let handler = JsonRpcHandler::new(None);
+            let request = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: Some(json!({
+                    "protocolVersion": requested,
+                    "capabilities": { "roots": { "listChanged": true } },
+                    "clientInfo": { "name": "test-client", "version": "0.1.0" }
+                })),
+            };
+
+            let response = handler.handle_request(request).await;
+
+            assert_eq!(response.result.unwrap()["protocolVersion"], expected);
+            let client = handler.client.read().unwrap();
+            assert_eq!(client.protocol_version.as_deref(), Some(expected));
+            assert_eq!(client.capabilities["roots"]["listChanged"], true);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initialize_advertises_only_implemented_capabilities() {
+        let handler = JsonRpcHandler::new(None);
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: Some(json!({ "protocolVersion": "2025-03-26" })),
+        };
+
+        let result = handler.handle_request(request).await.result.unwrap();
+        let capabilities = result["capabilities"].as_object().unwrap();
+        let mut advertised: Vec<&str> = capabilities.keys().map(String::as_str).collect();
+        advertised.sort();
+        assert_eq!(
+            advertised,
+            vec!["completions", "logging", "prompts", "resources", "tools"]
+        );
+        // Without a watched config file the tool list never changes
+        assert_eq!(capabilities["tools"]["listChanged"], false);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_follow_the_configuration() {
+        let initialize = |handler: JsonRpcHandler| async move {
+            let request = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: None,
+            };
+            handler.handle_request(request).await.result.unwrap()["capabilities"].clone()
+        };
+        let read_only = ToolSelection {
+            read_only: true,
+            ..ToolSelection::default()
+        };
+
+        // Read-only tools write no images, so there is nothing to list as resources
+        let capabilities = initialize(JsonRpcHandler::with_config(ServerConfig {
+            tools: read_only.clone(),
+            ..ServerConfig::default()
+        }))
+        .await;
+        assert!(capabilities.get("resources").is_none());
+
+        let capabilities = initialize(JsonRpcHandler::with_config(ServerConfig {
+            tools: read_only.clone(),
+            output_dirs: vec![PathBuf::from("test")],
+            ..ServerConfig::default()
+        }))
+        .await;
+        assert_eq!(capabilities["resources"]["listChanged"], true);
+
+        let (_selection_tx, selection_rx) = watch::channel(read_only);
+        let capabilities =
+            initialize(JsonRpcHandler::new(None).with_tool_selection(selection_rx)).await;
+        assert_eq!(capabilities["tools"]["listChanged"], true);
+        assert!(capabilities.get("resources").is_some());
     }
 
     #[tokio::test]
@@ -503,7 +1249,7 @@ mod tests {
 
         let result = response.result.unwrap();
         let tools = result["tools"].as_array().unwrap();
-        assert_eq!(tools.len(), 7);
+        assert_eq!(tools.len(), 8);
 
         assert_eq!(tools[0]["name"], "analyze_image");
         assert!(
@@ -515,6 +1261,9 @@ mod tests {
         assert!(tools[0]["inputSchema"]["properties"]["image_source"].is_object());
         assert!(tools[0]["inputSchema"]["properties"]["system_prompt"].is_object());
         assert!(tools[0]["inputSchema"]["properties"]["user_prompt"].is_object());
+        assert_eq!(tools[0]["outputSchema"]["type"], "object");
+        assert!(tools[0]["outputSchema"]["properties"]["text"].is_object());
+        assert!(tools[0]["outputSchema"]["properties"]["usage"].is_object());
 
         assert_eq!(tools[1]["name"], "generate_image");
         assert!(
@@ -525,6 +1274,16 @@ mod tests {
         );
         assert!(tools[1]["inputSchema"]["properties"]["user_prompt"].is_object());
         assert!(tools[1]["inputSchema"]["properties"]["system_prompt"].is_object());
+        for property in [
+            "file_path",
+            "mime_type",
+            "byte_size",
+            "width",
+            "height",
+            "model",
+        ] {
+            assert!(tools[1]["outputSchema"]["properties"][property].is_object());
+        }
 
         assert_eq!(tools[2]["name"], "edit_image");
         assert!(
@@ -1114,17 +1873,14 @@ mod tests {
         let response = handler.handle_request(request).await;
 
         assert_eq!(response.jsonrpc, "2.0");
-        assert!(response.result.is_none());
-        assert!(response.error.is_some());
+        assert!(response.error.is_none());
 
-        let error = response.error.unwrap();
-        // Since we have an API key but invalid input, this should be a validation error
-        // The actual error will depend on which validation fails first
-        assert!(error.code == -32602 || error.code == -32004); // Invalid params or file system error
-        assert!(
-            error.message.contains("Invalid arguments")
-                || error.message.contains("Unsupported file extension")
-        );
+        // Validation failures are execution errors the model can correct
+        let result = response.result.unwrap();
+        assert_eq!(result["isError"], true);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.contains("Unsupported file extension"));
+        assert!(text.contains("Fix the arguments"));
     }
 
     #[tokio::test]
@@ -1148,312 +1904,1114 @@ mod tests {
         let response = handler.handle_request(request).await;
 
         assert_eq!(response.jsonrpc, "2.0");
-        assert!(response.result.is_none());
-        assert!(response.error.is_some());
+        assert!(response.error.is_none());
 
-        let error = response.error.unwrap();
-        // Since we have an API key but invalid input, this should be a validation error
-        assert_eq!(error.code, -32602); // Invalid params 
-        // The error message should indicate the unsupported file extension
-        assert!(error.message.contains("Unsupported output file extension"));
+        // Validation failures are reported as tool results, not protocol errors
+        let result = response.result.unwrap();
+        assert_eq!(result["isError"], true);
+        assert!(
+            result["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Unsupported output file extension")
+        );
     }
-}
 
-impl JsonRpcHandler {
-    async fn handle_inpaint_image(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
-            }
-        };
+    /// A stand-in for the Gemini API that captions every image "A cat".
+    async fn gemini_stand_in() -> String {
+        let router = axum::Router::new().fallback(|| async {
+            axum::Json(json!({
+                "candidates": [{ "content": { "parts": [{ "text": "A cat" }] } }]
+            }))
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+        format!("http://{}/v1beta", address)
+    }
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<InpaintImageInput>(arguments.clone()) {
-                Ok(input) => match client.inpaint_image(&input).await {
-                    Ok(file_path) => {
-                        info!("Successfully inpainted and saved image to: {}", file_path);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Image successfully inpainted and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to inpaint image '{}' with prompt '{}': {}",
-                            input.image_source, input.user_prompt, e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for inpaint_image: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
-                }
-            }
-        } else {
-            JsonRpcResponse {
+    /// A handler limited to one Gemini call at a time, writing to `dir`, whose
+    /// client supports elicitation. Prompts stay unanswered unless a test answers them.
+    async fn handler_with_elicitation(
+        dir: &std::path::Path,
+    ) -> (
+        Arc<JsonRpcHandler>,
+        tokio::sync::mpsc::UnboundedReceiver<String>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = Arc::new(
+            JsonRpcHandler::with_config(ServerConfig {
+                api_key: Some("test-api-key".to_string()),
+                api_base_url: Some(gemini_stand_in().await),
+                max_concurrent_requests: 1,
+                allowed_dirs: vec![dir.to_path_buf(), PathBuf::from("test")],
+                ..ServerConfig::default()
+            })
+            .with_peer(Peer::new(tx)),
+        );
+        handler
+            .handle_request(JsonRpcRequest {
                 jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
+                id: Some(json!(0)),
+                method: "initialize".to_string(),
+                params: Some(json!({ "capabilities": { "elicitation": {} } })),
+            })
+            .await;
+        (handler, rx)
+    }
+
+    fn generate_image_to(id: i64, path: &std::path::Path) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(id)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "generate_image",
+                "arguments": {
+                    "user_prompt": "A lighthouse",
+                    "output_path": path.to_string_lossy()
+                }
+            })),
+        }
+    }
+
+    async fn next_request(rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>) -> Value {
+        loop {
+            let message: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            if message.get("id").is_some() {
+                return message;
             }
         }
     }
 
-    async fn handle_style_transfer(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
+    #[tokio::test]
+    async fn test_waiting_for_the_user_does_not_hold_a_gemini_permit() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("lighthouse.png");
+        std::fs::write(&existing, [0]).unwrap();
+        let (handler, mut rx) = handler_with_elicitation(dir.path()).await;
+
+        let prompted = tokio::spawn({
+            let handler = Arc::clone(&handler);
+            async move {
+                handler
+                    .handle_request(generate_image_to(1, &existing))
+                    .await
             }
+        });
+        assert_eq!(next_request(&mut rx).await["method"], "elicitation/create");
+
+        // The only permit is free while the overwrite prompt is pending
+        let analyze = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "analyze_image",
+                "arguments": { "image_source": "test/cat_image.jpg" }
+            })),
         };
+        let response = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            handler.handle_request(analyze),
+        )
+        .await
+        .unwrap();
+        let result = response.result.unwrap();
+        assert_ne!(result["isError"], true);
+        assert_eq!(result["content"][0]["text"], "A cat");
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<StyleTransferInput>(arguments.clone()) {
-                Ok(input) => match client.style_transfer(&input).await {
-                    Ok(file_path) => {
-                        info!(
-                            "Successfully applied style transfer and saved image to: {}",
-                            file_path
-                        );
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Style transfer completed and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to apply style transfer from '{}' to '{}': {}",
-                            input.style_image, input.source_image, e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for style_transfer: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
-                }
-            }
-        } else {
-            JsonRpcResponse {
+        assert!(!prompted.is_finished());
+        prompted.abort();
+    }
+
+    #[tokio::test]
+    async fn test_notifications_get_no_response() {
+        let handler = JsonRpcHandler::new(None);
+
+        for method in [
+            "notifications/initialized",
+            "notifications/cancelled",
+            "notifications/roots/list_changed",
+            "notifications/unknown",
+        ] {
+            let notification = JsonRpcRequest {
                 jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
-            }
+                id: None,
+                method: method.to_string(),
+                params: Some(json!({ "requestId": 3, "reason": "User cancelled" })),
+            };
+            assert!(handler.handle_message(notification).await.is_none());
         }
+
+        assert!(handler.initialized.load(Ordering::SeqCst));
     }
 
-    async fn handle_compose_images(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
+    #[tokio::test]
+    async fn test_request_without_id_is_treated_as_notification() {
+        let handler = JsonRpcHandler::new(None);
+        let request: JsonRpcRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","method":"unknown/method"}"#).unwrap();
+
+        assert!(handler.handle_message(request).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_message_responds_to_requests() {
+        let handler = JsonRpcHandler::new(None);
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!("abc")),
+            method: "unknown/method".to_string(),
+            params: None,
+        };
+
+        let response = handler.handle_message(request).await.unwrap();
+        assert_eq!(response.id, Some(json!("abc")));
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+
+    #[tokio::test]
+    async fn test_declined_overwrite_tells_the_model_not_to_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("lighthouse.png");
+        std::fs::write(&existing, [0]).unwrap();
+        let (handler, mut rx) = handler_with_elicitation(dir.path()).await;
+
+        let call = tokio::spawn({
+            let handler = Arc::clone(&handler);
+            let existing = existing.clone();
+            async move {
+                handler
+                    .handle_request(generate_image_to(1, &existing))
+                    .await
+            }
+        });
+        let prompt = next_request(&mut rx).await;
+        assert_eq!(prompt["method"], "elicitation/create");
+        let answer =
+            json!({ "jsonrpc": "2.0", "id": prompt["id"], "result": { "action": "decline" } });
+        let payload = JsonRpcPayload::parse(answer.to_string().as_bytes()).unwrap();
+        handler.handle_payload(payload).await;
+
+        let result = call.await.unwrap().result.unwrap();
+        assert_eq!(result["isError"], true);
+        let text = result["content"][0]["text"].as_str().unwrap();
+        assert!(text.starts_with("The user declined to overwrite"));
+        assert!(text.contains("do not retry with the same output_path"));
+        assert!(!text.contains("call the tool again"));
+        assert_eq!(std::fs::read(&existing).unwrap(), [0]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_gets_no_response() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("cancelled.png");
+        std::fs::write(&existing, [0]).unwrap();
+        // The unanswered overwrite prompt keeps the tool call in flight
+        let (handler, mut rx) = handler_with_elicitation(dir.path()).await;
+
+        let call = tokio::spawn({
+            let handler = Arc::clone(&handler);
+            let existing = existing.clone();
+            async move {
+                handler
+                    .handle_message(generate_image_to(7, &existing))
+                    .await
             }
+        });
+        assert_eq!(next_request(&mut rx).await["method"], "elicitation/create");
+        assert!(handler.in_flight.lock().unwrap().contains_key("7"));
+
+        let cancel = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": 7, "reason": "Took too long" })),
         };
+        assert!(handler.handle_message(cancel).await.is_none());
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<ComposeImagesInput>(arguments.clone()) {
-                Ok(input) => match client.compose_images(&input).await {
-                    Ok(file_path) => {
-                        info!("Successfully composed images and saved to: {}", file_path);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Images successfully composed and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to compose images with primary '{}' and {} secondary images: {}",
-                            input.primary_image,
-                            input.secondary_images.len(),
-                            e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
+        assert!(call.await.unwrap().is_none());
+        assert!(handler.in_flight.lock().unwrap().is_empty());
+        assert_eq!(std::fs::read(&existing).unwrap(), [0]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unknown_request_is_ignored() {
+        let handler = JsonRpcHandler::new(None);
+        let cancel = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": "does-not-exist" })),
+        };
+
+        assert!(handler.handle_message(cancel).await.is_none());
+        assert!(handler.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tools_call_reports_progress_with_token() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler =
+            JsonRpcHandler::new(Some("test-api-key".to_string())).with_peer(Peer::new(tx));
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "compose_images",
+                "arguments": {
+                    "primary_image": "./test/cat_image.jpg",
+                    "secondary_images": ["./test/missing.png"],
+                    "user_prompt": "Combine them",
+                    "output_path": "./test/composed.png"
                 },
-                Err(e) => {
-                    error!("Invalid arguments for compose_images: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
+                "_meta": { "progressToken": "compose-1" }
+            })),
+        };
+
+        let response = handler.handle_request(request).await;
+        assert_eq!(response.result.unwrap()["isError"], true);
+
+        let mut notifications = vec![];
+        while let Ok(message) = rx.try_recv() {
+            notifications.push(serde_json::from_str::<Value>(&message).unwrap());
+        }
+        let messages: Vec<&str> = notifications
+            .iter()
+            .map(|n| {
+                assert_eq!(n["method"], "notifications/progress");
+                assert_eq!(n["params"]["progressToken"], "compose-1");
+                n["params"]["message"].as_str().unwrap()
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Validating input",
+                "Fetching image 1 of 2: ./test/cat_image.jpg",
+                "Fetching image 2 of 2: ./test/missing.png",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tools_call_without_token_reports_no_progress() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler =
+            JsonRpcHandler::new(Some("test-api-key".to_string())).with_peer(Peer::new(tx));
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "edit_image",
+                "arguments": {
+                    "image_source": "./test/missing.png",
+                    "user_prompt": "Add a hat",
+                    "output_path": "./test/edited.png"
                 }
+            })),
+        };
+
+        handler.handle_request(request).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_omits_notification_responses() {
+        let handler = JsonRpcHandler::new(None);
+        let payload = JsonRpcPayload::parse(
+            br#"[
+                {"jsonrpc": "2.0", "id": 1, "method": "tools/list"},
+                {"jsonrpc": "2.0", "method": "notifications/initialized"},
+                {"jsonrpc": "2.0", "id": "two", "method": "unknown/method"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(!payload.is_notification_only());
+
+        let Some(JsonRpcReply::Batch(responses)) = handler.handle_payload(payload).await else {
+            panic!("expected a batch reply");
+        };
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].id, Some(json!(1)));
+        assert!(responses[0].result.is_some());
+        assert_eq!(responses[1].id, Some(json!("two")));
+        assert_eq!(responses[1].error.as_ref().unwrap().code, -32601);
+    }
+
+    #[tokio::test]
+    async fn test_batch_of_notifications_gets_no_reply() {
+        let handler = JsonRpcHandler::new(None);
+        let payload = JsonRpcPayload::parse(
+            br#"[
+                {"jsonrpc": "2.0", "method": "notifications/initialized"},
+                {"jsonrpc": "2.0", "method": "notifications/roots/list_changed"}
+            ]"#,
+        )
+        .unwrap();
+        assert!(payload.is_notification_only());
+
+        assert!(handler.handle_payload(payload).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_invalid_entries() {
+        let handler = JsonRpcHandler::new(None);
+
+        let payload = JsonRpcPayload::parse(b"[]").unwrap();
+        let Some(JsonRpcReply::Single(response)) = handler.handle_payload(payload).await else {
+            panic!("expected a single error reply");
+        };
+        assert_eq!(response.error.unwrap().code, -32600);
+
+        let payload =
+            JsonRpcPayload::parse(br#"[1, {"jsonrpc": "2.0", "id": 5, "params": {}}]"#).unwrap();
+        let reply = handler.handle_payload(payload).await.unwrap();
+        let reply: Value = serde_json::from_str(&reply.to_json()).unwrap();
+        assert_eq!(reply.as_array().unwrap().len(), 2);
+        assert_eq!(reply[0]["error"]["code"], -32600);
+        assert_eq!(reply[0]["id"], Value::Null);
+        assert_eq!(reply[1]["error"]["code"], -32600);
+        assert_eq!(reply[1]["id"], 5);
+    }
+
+    #[test]
+    fn test_payload_parse_errors() {
+        let error = JsonRpcPayload::parse(b"{invalid json").unwrap_err();
+        assert_eq!(error.error.unwrap().code, -32700);
+
+        let error = JsonRpcPayload::parse(br#"{"jsonrpc": "2.0", "id": 3}"#).unwrap_err();
+        assert_eq!(error.id, Some(json!(3)));
+        assert_eq!(error.error.unwrap().code, -32600);
+    }
+
+    #[tokio::test]
+    async fn test_generated_image_result_attaches_downscaled_image() {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(300, 150)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let generated = GeneratedImage {
+            file_path: "./out.png".to_string(),
+            mime_type: "image/png".to_string(),
+            data: png.into_inner(),
+            model: "test-model".to_string(),
+            generation_config: None,
+            usage: None,
+        };
+        let handler = JsonRpcHandler::with_config(ServerConfig {
+            image_max_dimension: Some(60),
+            ..ServerConfig::default()
+        });
+
+        let without_image = handler
+            .generated_image_result("saved".to_string(), generated.clone(), None)
+            .await;
+        assert_eq!(without_image["content"].as_array().unwrap().len(), 1);
+        assert_eq!(without_image["structuredContent"]["file_path"], "./out.png");
+        assert_eq!(without_image["structuredContent"]["width"], 300);
+        assert_eq!(without_image["structuredContent"]["height"], 150);
+        assert_eq!(without_image["structuredContent"]["model"], "test-model");
+
+        let with_image = handler
+            .generated_image_result("saved".to_string(), generated, Some(true))
+            .await;
+        let block = &with_image["content"][1];
+        assert_eq!(block["type"], "image");
+        assert_eq!(block["mimeType"], "image/png");
+        let data = general_purpose::STANDARD
+            .decode(block["data"].as_str().unwrap())
+            .unwrap();
+        let attached = image::load_from_memory(&data).unwrap();
+        assert_eq!((attached.width(), attached.height()), (60, 30));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_arguments_stay_protocol_errors() {
+        let handler = JsonRpcHandler::new(Some("test-api-key".to_string()));
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "generate_image",
+                "arguments": { "user_prompt": 42 }
+            })),
+        };
+
+        let response = handler.handle_request(request).await;
+
+        assert!(response.result.is_none());
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_tool_error_result_is_actionable() {
+        let result = tool_error_result(&McpError::RateLimitError("quota".to_string()));
+        assert_eq!(result["isError"], true);
+        assert_eq!(result["content"][0]["type"], "text");
+        assert!(
+            result["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Wait a minute before retrying")
+        );
+
+        let result = tool_error_result(&McpError::GeminiApiError {
+            code: 400,
+            message: "Request blocked by safety filters".to_string(),
+        });
+        assert!(
+            result["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("rephrase the prompt")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tools_list_includes_annotations() {
+        let handler = JsonRpcHandler::new(None);
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+
+        let result = handler.handle_request(request).await.result.unwrap();
+        let tools = result["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 8);
+
+        for tool in tools {
+            let annotations = &tool["annotations"];
+            assert!(tool["title"].is_string());
+            assert_eq!(annotations["title"], tool["title"]);
+            assert_eq!(annotations["openWorldHint"], true);
+            if tool["name"] == "analyze_image" || tool["name"] == "list_models" {
+                assert_eq!(annotations["readOnlyHint"], true);
+                assert_eq!(annotations["idempotentHint"], true);
+            } else {
+                assert_eq!(annotations["readOnlyHint"], false);
+                assert_eq!(annotations["destructiveHint"], true);
             }
-        } else {
-            JsonRpcResponse {
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_log_level() {
+        let handler = JsonRpcHandler::new(None);
+        let request = |level: Value| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "logging/setLevel".to_string(),
+            params: Some(json!({ "level": level })),
+        };
+
+        let response = handler.handle_request(request(json!("debug"))).await;
+        assert_eq!(response.result, Some(json!({})));
+
+        let response = handler.handle_request(request(json!("loud"))).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_log_events_are_forwarded_to_the_requesting_session() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber =
+            tracing_subscriber::registry().with(crate::logging::ClientLogLayer::for_crate());
+        let _default = tracing::subscriber::set_default(subscriber);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = JsonRpcHandler::new(None).with_peer(Peer::new(tx));
+
+        handler
+            .handle_message(JsonRpcRequest {
                 jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
-            }
+                id: Some(json!(1)),
+                method: "logging/setLevel".to_string(),
+                params: Some(json!({ "level": "info" })),
+            })
+            .await;
+        handler
+            .handle_message(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: None,
+                method: "bogus/notification".to_string(),
+                params: None,
+            })
+            .await;
+
+        let message: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/message");
+        assert_eq!(message["params"]["level"], "warning");
+        assert_eq!(message["params"]["logger"], "jsonrpc");
+        assert!(
+            message["params"]["data"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("bogus/notification")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_logs_carry_id_and_tool() {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = Arc::clone(&output);
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || WriteInto(Arc::clone(&writer)))
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+        let handler = JsonRpcHandler::new(Some("test-api-key".to_string()));
+
+        handler
+            .handle_message(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(5)),
+                method: "tools/call".to_string(),
+                params: Some(json!({
+                    "name": "generate_image",
+                    "arguments": { "user_prompt": 42 }
+                })),
+            })
+            .await;
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.contains("Invalid arguments for generate_image"))
+            .unwrap();
+        assert!(line.contains("request{id=5 method=tools/call tool=\"generate_image\"}"));
+    }
+
+    struct WriteInto(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for WriteInto {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
 
-    async fn handle_refine_image(&self, id: Option<Value>, tool_call: Value) -> JsonRpcResponse {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(convert_mcp_error_to_jsonrpc(McpError::ConfigurationError(
-                        "GEMINI_API_KEY environment variable not set".to_string(),
-                    ))),
-                };
-            }
+    #[tokio::test]
+    async fn test_generated_images_become_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("generated.png");
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = JsonRpcHandler::new(None).with_peer(Peer::new(tx));
+        let generated = GeneratedImage {
+            file_path: path.to_string_lossy().into_owned(),
+            mime_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+            model: "test-model".to_string(),
+            generation_config: None,
+            usage: None,
         };
 
-        if let Some(arguments) = tool_call.get("arguments") {
-            match serde_json::from_value::<RefineImageInput>(arguments.clone()) {
-                Ok(input) => match client.refine_image(&input).await {
-                    Ok(file_path) => {
-                        info!("Successfully refined image and saved to: {}", file_path);
-                        let result = json!({
-                            "content": [
-                                {
-                                    "type": "text",
-                                    "text": format!("Image successfully refined and saved to: {}", file_path)
-                                }
-                            ],
-                            "file_path": file_path
-                        });
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: Some(result),
-                            error: None,
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to refine image '{}' with prompt '{}': {}",
-                            input.image_source, input.user_prompt, e
-                        );
-                        JsonRpcResponse {
-                            jsonrpc: "2.0".to_string(),
-                            id,
-                            result: None,
-                            error: Some(convert_mcp_error_to_jsonrpc(e)),
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Invalid arguments for refine_image: {}", e);
-                    JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        id,
-                        result: None,
-                        error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                            format!("Invalid arguments: {}", e),
-                        ))),
-                    }
+        handler
+            .generated_image_result("saved".to_string(), generated, None)
+            .await;
+
+        let notification: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(
+            notification["method"],
+            "notifications/resources/list_changed"
+        );
+
+        let list = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "resources/list".to_string(),
+                params: None,
+            })
+            .await
+            .result
+            .unwrap();
+        let uri = list["resources"][0]["uri"].as_str().unwrap();
+        assert!(uri.starts_with("gemini-image://generations/"));
+
+        let read = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(2)),
+                method: "resources/read".to_string(),
+                params: Some(json!({ "uri": uri })),
+            })
+            .await
+            .result
+            .unwrap();
+        assert_eq!(read["contents"][0]["mimeType"], "image/png");
+        assert_eq!(read["contents"][0]["blob"], "AQID");
+
+        let missing = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(3)),
+                method: "resources/read".to_string(),
+                params: Some(json!({ "uri": "gemini-image://generations/nope" })),
+            })
+            .await;
+        assert_eq!(missing.error.unwrap().code, -32002);
+    }
+
+    #[tokio::test]
+    async fn test_prompts_list_and_get() {
+        let handler = JsonRpcHandler::new(None);
+
+        let list = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "prompts/list".to_string(),
+                params: None,
+            })
+            .await
+            .result
+            .unwrap();
+        let names: Vec<&str> = list["prompts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|prompt| prompt["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"ui_review"));
+        assert!(names.contains(&"house_style"));
+
+        let get = |params: Value| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "prompts/get".to_string(),
+            params: Some(params),
+        };
+        let result = handler
+            .handle_request(get(json!({
+                "name": "ui_review",
+                "arguments": { "image_source": "./screen.png", "focus": "forms" }
+            })))
+            .await
+            .result
+            .unwrap();
+        assert_eq!(result["messages"][0]["role"], "user");
+        assert!(
+            result["messages"][0]["content"]["text"]
+                .as_str()
+                .unwrap()
+                .contains("./screen.png")
+        );
+
+        let missing_argument = handler
+            .handle_request(get(json!({ "name": "ui_review" })))
+            .await;
+        assert_eq!(missing_argument.error.unwrap().code, -32602);
+
+        let unknown = handler.handle_request(get(json!({ "name": "nope" }))).await;
+        assert_eq!(unknown.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_completion_complete() {
+        let handler = JsonRpcHandler::with_config(ServerConfig {
+            output_dirs: vec![PathBuf::from("renders")],
+            ..ServerConfig::default()
+        });
+        let complete = |reference: Value, name: &str, value: &str| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "completion/complete".to_string(),
+            params: Some(json!({
+                "ref": reference,
+                "argument": { "name": name, "value": value }
+            })),
+        };
+        let prompt = json!({ "type": "ref/prompt", "name": "product_photo" });
+
+        // Sample images in the repository's test directory
+        let images = handler
+            .handle_request(complete(prompt.clone(), "image_source", "test/"))
+            .await
+            .result
+            .unwrap();
+        let values = images["completion"]["values"].as_array().unwrap();
+        assert!(!values.is_empty());
+        assert!(
+            values
+                .iter()
+                .all(|v| v.as_str().unwrap().starts_with("test/"))
+        );
+
+        let output = handler
+            .handle_request(complete(prompt.clone(), "output_path", "ren"))
+            .await
+            .result
+            .unwrap();
+        assert_eq!(output["completion"]["values"], json!(["renders/"]));
+
+        let presets = handler
+            .handle_request(complete(prompt, "background", "plain"))
+            .await
+            .result
+            .unwrap();
+        assert_eq!(presets["completion"]["values"], json!(["plain white"]));
+
+        let invalid = handler
+            .handle_request(complete(json!({ "type": "ref/tool" }), "x", ""))
+            .await;
+        assert_eq!(invalid.error.unwrap().code, -32602);
+    }
+
+    /// Answers the next `count` `roots/list` requests with `root`.
+    fn answer_roots_requests(
+        handler: Arc<JsonRpcHandler>,
+        mut rx: tokio::sync::mpsc::UnboundedReceiver<String>,
+        root: PathBuf,
+        count: usize,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut answered = 0;
+            while answered < count {
+                let message: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+                if message["method"] != "roots/list" {
+                    continue;
                 }
+                answered += 1;
+                let uri = reqwest::Url::from_directory_path(&root).unwrap();
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "result": { "roots": [{ "uri": uri.as_str() }] }
+                });
+                let payload = JsonRpcPayload::parse(response.to_string().as_bytes()).unwrap();
+                assert!(payload.is_notification_only());
+                assert!(handler.handle_payload(payload).await.is_none());
             }
-        } else {
-            JsonRpcResponse {
+        })
+    }
+
+    #[tokio::test]
+    async fn test_file_access_is_confined_to_client_roots() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = Arc::new(
+            JsonRpcHandler::new(Some("test-api-key".to_string())).with_peer(Peer::new(tx)),
+        );
+        handler
+            .handle_request(JsonRpcRequest {
                 jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(convert_mcp_error_to_jsonrpc(McpError::InvalidInput(
-                    "Missing arguments".to_string(),
-                ))),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: Some(json!({ "capabilities": { "roots": { "listChanged": true } } })),
+            })
+            .await;
+        let responder =
+            answer_roots_requests(Arc::clone(&handler), rx, root.path().to_path_buf(), 2);
+
+        let generate_to = |path: PathBuf| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "generate_image",
+                "arguments": {
+                    "user_prompt": "A cat",
+                    "output_path": path.join("cat.png").to_string_lossy()
+                }
+            })),
+        };
+
+        let response = handler
+            .handle_request(generate_to(outside.path().into()))
+            .await;
+        let result = response.result.unwrap();
+        assert_eq!(result["isError"], true);
+        assert!(
+            result["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("outside the allowed roots")
+        );
+
+        // Roots are cached until the client says they changed
+        assert!(
+            handler
+                .file_access()
+                .await
+                .check_write(&root.path().join("cat.png").to_string_lossy())
+                .await
+                .is_ok()
+        );
+        handler
+            .handle_message(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: None,
+                method: "notifications/roots/list_changed".to_string(),
+                params: None,
+            })
+            .await;
+        handler.file_access().await;
+
+        // One request for the first file access and one after the change
+        tokio::time::timeout(std::time::Duration::from_secs(5), responder)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_completion_is_relative_to_client_roots() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("kitten.png"), [0]).unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = Arc::new(
+            JsonRpcHandler::new(Some("test-api-key".to_string())).with_peer(Peer::new(tx)),
+        );
+        handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: Some(json!({ "capabilities": { "roots": { "listChanged": true } } })),
+            })
+            .await;
+        let responder =
+            answer_roots_requests(Arc::clone(&handler), rx, root.path().to_path_buf(), 1);
+
+        let response = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(2)),
+                method: "completion/complete".to_string(),
+                params: Some(json!({
+                    "ref": { "type": "ref/prompt", "name": "product_photo" },
+                    "argument": { "name": "image_source", "value": "kit" }
+                })),
+            })
+            .await;
+        assert_eq!(
+            response.result.unwrap()["completion"]["values"],
+            json!(["kitten.png"])
+        );
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), responder)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_roots_listing_is_not_retried_until_roots_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = Arc::new(
+            JsonRpcHandler::with_config(ServerConfig {
+                allowed_dirs: vec![dir.path().to_path_buf()],
+                ..ServerConfig::default()
+            })
+            .with_peer(Peer::new(tx)),
+        );
+        handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "initialize".to_string(),
+                params: Some(json!({ "capabilities": { "roots": { "listChanged": true } } })),
+            })
+            .await;
+        let listings = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        tokio::spawn({
+            let (handler, listings) = (Arc::clone(&handler), Arc::clone(&listings));
+            async move {
+                while let Some(message) = rx.recv().await {
+                    let message: Value = serde_json::from_str(&message).unwrap();
+                    if message["method"] != "roots/list" {
+                        continue;
+                    }
+                    listings.fetch_add(1, Ordering::SeqCst);
+                    let response = json!({
+                        "jsonrpc": "2.0",
+                        "id": message["id"],
+                        "error": { "code": -32603, "message": "roots unavailable" }
+                    });
+                    let payload = JsonRpcPayload::parse(response.to_string().as_bytes()).unwrap();
+                    handler.handle_payload(payload).await;
+                }
             }
+        });
+
+        let canonical = dir.path().canonicalize().unwrap();
+        for _ in 0..3 {
+            assert_eq!(
+                handler.file_access().await.roots(),
+                std::slice::from_ref(&canonical)
+            );
         }
+        assert_eq!(listings.load(Ordering::SeqCst), 1);
+
+        handler
+            .handle_message(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: None,
+                method: "notifications/roots/list_changed".to_string(),
+                params: None,
+            })
+            .await;
+        handler.file_access().await;
+        assert_eq!(listings.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_file_access_without_client_roots_uses_allowed_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = JsonRpcHandler::with_config(ServerConfig {
+            allowed_dirs: vec![dir.path().to_path_buf()],
+            ..ServerConfig::default()
+        });
+
+        let files = handler.file_access().await;
+
+        assert_eq!(files.roots(), [dir.path().canonicalize().unwrap()]);
+        assert!(files.check_read("test/cat_image.jpg").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tool_selection_changes_are_applied_and_announced() {
+        let (selection_tx, selection_rx) = watch::channel(ToolSelection::default());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = Arc::new(
+            JsonRpcHandler::new(Some("test-api-key".to_string()))
+                .with_tool_selection(selection_rx)
+                .with_peer(Peer::new(tx)),
+        );
+        let forwarder = tokio::spawn({
+            let handler = Arc::clone(&handler);
+            async move { handler.forward_tool_list_changes().await }
+        });
+
+        selection_tx.send_replace(ToolSelection {
+            read_only: true,
+            ..ToolSelection::default()
+        });
+        let message: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/tools/list_changed");
+
+        let result = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "tools/list".to_string(),
+                params: None,
+            })
+            .await
+            .result
+            .unwrap();
+        assert_eq!(result["tools"].as_array().unwrap().len(), 2);
+
+        let response = handler
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(2)),
+                method: "tools/call".to_string(),
+                params: Some(json!({
+                    "name": "generate_image",
+                    "arguments": { "user_prompt": "A cat", "output_path": "cat.png" }
+                })),
+            })
+            .await;
+        assert!(response.error.unwrap().message.contains("disabled"));
+
+        // The forwarder stops once the selection can no longer change
+        drop(selection_tx);
+        forwarder.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_models_filters_and_caches_the_listing() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let router = axum::Router::new().route(
+            "/v1beta/models",
+            axum::routing::get(
+                move |axum::extract::Query(query): axum::extract::Query<
+                    HashMap<String, String>,
+                >| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let methods = ["generateContent", "countTokens"];
+                        axum::Json(match query.get("pageToken") {
+                            None => json!({
+                                "models": [{
+                                    "name": "models/gemini-2.5-flash",
+                                    "displayName": "Gemini 2.5 Flash",
+                                    "inputTokenLimit": 1048576,
+                                    "outputTokenLimit": 65536,
+                                    "supportedGenerationMethods": methods
+                                }],
+                                "nextPageToken": "page-2"
+                            }),
+                            Some(_) => json!({
+                                "models": [{
+                                    "name": "models/gemini-2.5-flash-image-preview",
+                                    "displayName": "Nano Banana",
+                                    "supportedGenerationMethods": methods
+                                }]
+                            }),
+                        })
+                    }
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        let handler = JsonRpcHandler::with_config(ServerConfig {
+            api_key: Some("test-key".to_string()),
+            api_base_url: Some(format!("http://{}/v1beta", address)),
+            ..ServerConfig::default()
+        });
+        let list_models = |arguments: Value| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": "list_models", "arguments": arguments })),
+        };
+
+        let result = handler
+            .handle_request(list_models(json!({ "output_modality": "image" })))
+            .await
+            .result
+            .unwrap();
+        let models = result["structuredContent"]["models"].as_array().unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0]["name"], "gemini-2.5-flash-image-preview");
+        assert_eq!(models[0]["output_modalities"], json!(["text", "image"]));
+
+        let result = handler
+            .handle_request(list_models(Value::Null))
+            .await
+            .result
+            .unwrap();
+        let models = result["structuredContent"]["models"].as_array().unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0]["input_token_limit"], 1048576);
+        assert!(
+            result["content"][0]["text"].as_str().unwrap().contains(
+                "gemini-2.5-flash (Gemini 2.5 Flash): 1048576 input / 65536 output tokens"
+            )
+        );
+        // Both pages were fetched once; the second call was served from the cache
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
     }
 }
@@ -0,0 +1,26 @@
+//! MCP server for image analysis and generation with Google's Gemini API.
+//!
+//! The `gemini-image-mcp` binary wires these modules together. Crates that want
+//! to serve extra tools build a [`jsonrpc::JsonRpcHandler`], add their
+//! [`tools::Tool`] implementations with `with_tool`, and hand it to a transport.
+
+pub mod config;
+pub mod error;
+pub mod gemini_client;
+pub mod http_transport;
+pub mod jsonrpc;
+pub mod logging;
+pub mod models;
+pub mod peer;
+pub mod progress;
+pub mod roots;
+pub mod stdio_transport;
+pub mod tools;
+pub mod vertex;
+
+mod completion;
+mod gemini_tools;
+mod image_service;
+mod prompts;
+mod resources;
+mod validation;
@@ -0,0 +1,242 @@
+use crate::peer::Peer;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target prefix of this crate's tracing events; only these are sent to clients
+const CRATE_TARGET: &str = "gemini_image_mcp";
+
+/// Syslog-style severities used by MCP `logging/setLevel` and `notifications/message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::TRACE | Level::DEBUG => LogLevel::Debug,
+            Level::INFO => LogLevel::Info,
+            Level::WARN => LogLevel::Warning,
+            Level::ERROR => LogLevel::Error,
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Logger of the session whose request is being handled
+    static CLIENT_LOGGER: ClientLogger;
+}
+
+thread_local! {
+    /// Set while an event is being forwarded, so events emitted by the peer
+    /// itself are not forwarded again
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Forwards log events to one client session as `notifications/message`.
+///
+/// Nothing is sent until the client picks a level with `logging/setLevel`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientLogger {
+    level: Arc<Mutex<Option<LogLevel>>>,
+    peer: Peer,
+}
+
+impl ClientLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        *self.level.lock().unwrap() = Some(level);
+    }
+
+    /// Returns a logger sharing this session's level that sends to `peer`.
+    pub fn with_peer(&self, peer: Peer) -> Self {
+        Self {
+            level: Arc::clone(&self.level),
+            peer,
+        }
+    }
+
+    /// Runs `future` with this logger receiving the events it emits.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CLIENT_LOGGER.scope(self, future).await
+    }
+
+    fn log(&self, level: LogLevel, logger: &str, data: Value) {
+        if self.level.lock().unwrap().is_none_or(|min| level < min) {
+            return;
+        }
+
+        self.peer.notify(
+            "notifications/message",
+            json!({
+                "level": level,
+                "logger": logger,
+                "data": data,
+            }),
+        );
+    }
+}
+
+/// Tracing layer that hands this crate's events to the logger of the session
+/// whose request emitted them.
+pub struct ClientLogLayer;
+
+impl ClientLogLayer {
+    /// The layer filtered to this crate's events, so callsites in dependencies
+    /// stay disabled unless another layer wants them.
+    pub fn for_crate<S>() -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        ClientLogLayer.with_filter(filter_fn(|metadata| {
+            logger_name(metadata.target()).is_some()
+        }))
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let Some(logger) = logger_name(metadata.target()) else {
+            return;
+        };
+        if FORWARDING.get() {
+            return;
+        }
+
+        let _ = CLIENT_LOGGER.try_with(|client_logger| {
+            let mut visitor = JsonVisitor::default();
+            event.record(&mut visitor);
+
+            FORWARDING.set(true);
+            client_logger.log(
+                LogLevel::from(metadata.level()),
+                logger,
+                Value::Object(visitor.0),
+            );
+            FORWARDING.set(false);
+        });
+    }
+}
+
+/// Maps an event target such as `gemini_image_mcp::gemini_client` to the module name.
+fn logger_name(target: &str) -> Option<&str> {
+    let module = target.strip_prefix(CRATE_TARGET)?;
+    match module.strip_prefix("::") {
+        Some(path) => path.split("::").next(),
+        None if module.is_empty() => Some(CRATE_TARGET),
+        None => None,
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_log_level_ordering_and_names() {
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert!(LogLevel::Warning < LogLevel::Emergency);
+        assert_eq!(
+            serde_json::from_value::<LogLevel>(json!("warning")).unwrap(),
+            LogLevel::Warning
+        );
+        assert_eq!(LogLevel::from(&Level::WARN), LogLevel::Warning);
+        assert!(serde_json::from_value::<LogLevel>(json!("verbose")).is_err());
+    }
+
+    #[test]
+    fn test_logger_name_uses_module() {
+        assert_eq!(
+            logger_name("gemini_image_mcp::gemini_client"),
+            Some("gemini_client")
+        );
+        assert_eq!(
+            logger_name("gemini_image_mcp::logging::tests"),
+            Some("logging")
+        );
+        assert_eq!(logger_name("gemini_image_mcp"), Some("gemini_image_mcp"));
+        assert_eq!(logger_name("gemini_image_mcpx"), None);
+        assert_eq!(logger_name("hyper::client"), None);
+    }
+
+    #[tokio::test]
+    async fn test_layer_forwards_events_at_or_above_level() {
+        let subscriber = tracing_subscriber::registry().with(ClientLogLayer::for_crate());
+        let _default = tracing::subscriber::set_default(subscriber);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let logger = ClientLogger::new().with_peer(Peer::new(tx));
+
+        // Nothing is forwarded before the client sets a level
+        logger
+            .clone()
+            .scope(async { tracing::error!("before setLevel") })
+            .await;
+        assert!(rx.try_recv().is_err());
+
+        logger.set_level(LogLevel::Warning);
+        logger
+            .scope(async {
+                tracing::info!("too verbose");
+                tracing::warn!(path = "out.png", "disk almost full");
+            })
+            .await;
+        // Outside a request scope nothing is forwarded
+        tracing::error!("no session");
+
+        let message: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/message");
+        assert_eq!(message["params"]["level"], "warning");
+        assert_eq!(message["params"]["logger"], "logging");
+        assert_eq!(message["params"]["data"]["message"], "disk almost full");
+        assert_eq!(message["params"]["data"]["path"], "out.png");
+        assert!(rx.try_recv().is_err());
+    }
+}
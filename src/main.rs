@@ -1,15 +1,42 @@
-use anyhow::Result;
-use clap::Parser;
-use std::io::{self, BufRead, Write};
-use tracing::{error, info, warn};
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
 
-mod error;
-mod gemini_client;
-mod image_service;
-mod jsonrpc;
-mod validation;
+use gemini_image_mcp::config::{
+    DEFAULT_MAX_CONCURRENT_REQUESTS, ServerConfig, watch_tool_selection,
+};
+use gemini_image_mcp::gemini_client::{
+    DEFAULT_ANALYSIS_MODEL, DEFAULT_IMAGE_MODEL, GenerationConfig, parse_api_base_url,
+};
+use gemini_image_mcp::jsonrpc::JsonRpcHandler;
+use gemini_image_mcp::models::DEFAULT_MODELS_CACHE_TTL;
+use gemini_image_mcp::roots::OverwritePolicy;
+use gemini_image_mcp::tools::ToolSelection;
+use gemini_image_mcp::vertex::{DEFAULT_LOCATION, ServiceAccountKey, VertexConfig};
+use gemini_image_mcp::{http_transport, logging, stdio_transport};
 
-use jsonrpc::{JsonRpcHandler, JsonRpcRequest, JsonRpcResponse};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Transport {
+    /// Newline-delimited JSON-RPC over stdin/stdout
+    Stdio,
+    /// MCP Streamable HTTP on the address given by --listen
+    Http,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, including the current request span
+    Json,
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -17,6 +44,174 @@ struct Args {
     /// Override GEMINI_API_KEY environment variable with this API key
     #[arg(long, value_name = "KEY")]
     gemini_api_key: Option<String>,
+
+    /// Send Gemini API calls to this URL instead of the public endpoint; plain
+    /// http is accepted for localhost [env: GEMINI_API_BASE_URL]
+    #[arg(long, value_name = "URL")]
+    api_base_url: Option<String>,
+
+    /// Call Gemini through Vertex AI in this Google Cloud project instead of AI Studio
+    #[arg(long, value_name = "PROJECT")]
+    vertex_project: Option<String>,
+
+    /// Vertex AI region, or `global`
+    #[arg(long, value_name = "LOCATION", default_value = DEFAULT_LOCATION)]
+    vertex_location: String,
+
+    /// Service-account JSON key used for Vertex AI
+    /// [env: GOOGLE_APPLICATION_CREDENTIALS]
+    #[arg(long, value_name = "FILE")]
+    vertex_credentials: Option<PathBuf>,
+
+    /// OAuth token endpoint for Vertex AI; defaults to the key's token_uri
+    #[arg(long, value_name = "URL")]
+    vertex_token_url: Option<String>,
+
+    /// Model analyze_image uses when a call doesn't set `model`
+    #[arg(long, value_name = "MODEL", default_value = DEFAULT_ANALYSIS_MODEL)]
+    analysis_model: String,
+
+    /// Model the image generation and editing tools use when a call doesn't set `model`
+    #[arg(long, value_name = "MODEL", default_value = DEFAULT_IMAGE_MODEL)]
+    image_model: String,
+
+    /// Default sampling temperature (0.0-2.0) for calls whose generation_config doesn't set one
+    #[arg(long, value_name = "T")]
+    temperature: Option<f64>,
+
+    /// Default nucleus sampling probability (0.0-1.0)
+    #[arg(long, value_name = "P")]
+    top_p: Option<f64>,
+
+    /// Default number of most likely tokens sampled from
+    #[arg(long, value_name = "K")]
+    top_k: Option<u32>,
+
+    /// Default seed, for reproducible output
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    seed: Option<i32>,
+
+    /// Default upper bound on tokens in a response
+    #[arg(long, value_name = "N")]
+    max_output_tokens: Option<u32>,
+
+    /// Seconds list_models reuses a listing of the models endpoint
+    #[arg(long, value_name = "SECS", default_value_t = DEFAULT_MODELS_CACHE_TTL.as_secs())]
+    models_cache_ttl: u64,
+
+    /// Maximum number of Gemini API calls processed concurrently
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_CONCURRENT_REQUESTS,
+          value_parser = parse_max_concurrent_requests)]
+    max_concurrent_requests: usize,
+
+    /// Return generated images as image content blocks unless a call sets include_image
+    #[arg(long)]
+    include_image: bool,
+
+    /// Downscale images returned as content blocks so neither side exceeds N pixels
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    image_max_dimension: Option<u32>,
+
+    /// Directory whose images are exposed as resources (may be repeated)
+    #[arg(long = "output-dir", value_name = "DIR")]
+    output_dirs: Vec<PathBuf>,
+
+    /// Directory of .toml/.md prompt templates to serve alongside the built-in prompts
+    #[arg(long, value_name = "DIR")]
+    prompts_dir: Option<PathBuf>,
+
+    /// Directory tools may read and write when the client doesn't share roots
+    /// (may be repeated; defaults to the working directory)
+    #[arg(long = "allowed-dir", value_name = "DIR")]
+    allowed_dirs: Vec<PathBuf>,
+
+    /// What generator tools do when output_path exists and the call doesn't set
+    /// overwrite_policy; `ask` overwrites for clients without elicitation
+    #[arg(long, value_enum, default_value_t = OverwritePolicy::Ask)]
+    overwrite_policy: OverwritePolicy,
+
+    /// Offer only these tools (comma-separated or repeated)
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',')]
+    enable_tools: Option<Vec<String>>,
+
+    /// Never offer these tools (comma-separated or repeated)
+    #[arg(long, value_name = "TOOLS", value_delimiter = ',')]
+    disable_tools: Vec<String>,
+
+    /// Offer only read-only tools, so nothing is written and no generation quota is spent
+    #[arg(long)]
+    read_only: bool,
+
+    /// TOML file whose [tools] table overrides the tool flags; reloaded when it changes
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Transport used to talk to MCP clients
+    #[arg(long, value_enum, default_value_t = Transport::Stdio)]
+    transport: Transport,
+
+    /// Address the HTTP transport listens on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// Write logs to this file, rotated daily, instead of stderr
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Format of the server logs
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+fn parse_max_concurrent_requests(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Sets up server logs. They never go to stdout, which carries the stdio transport.
+fn init_logging(log_file: Option<&Path>, format: LogFormat) -> Result<Option<WorkerGuard>> {
+    let (writer, guard) = match log_file {
+        Some(path) => {
+            let file_name = path
+                .file_name()
+                .with_context(|| format!("Invalid log file path: {}", path.display()))?;
+            let directory = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::daily(
+                directory, file_name,
+            ));
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), None),
+    };
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    let ansi = log_file.is_none();
+    let fmt_layer = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(filter))
+        .with(logging::ClientLogLayer::for_crate())
+        .init();
+    Ok(guard)
 }
 
 #[tokio::main]
@@ -24,14 +219,17 @@ async fn main() -> Result<()> {
     // Load .env file if it exists
     dotenvy::dotenv().ok();
 
-    tracing_subscriber::fmt::init();
-
     let args = Args::parse();
+    // Flushes buffered file logs when dropped at the end of main
+    let _log_guard = init_logging(args.log_file.as_deref(), args.log_format)?;
 
     info!("Starting Gemini Image Analysis MCP Server");
 
-    // Determine API key: command line takes precedence over environment variable
-    let api_key = if let Some(key) = args.gemini_api_key {
+    // Determine API key: command line takes precedence over environment variable.
+    // Vertex AI authenticates with a service account instead.
+    let api_key = if args.vertex_project.is_some() {
+        None
+    } else if let Some(key) = args.gemini_api_key {
         if key.trim().is_empty() {
             warn!("Command line API key is empty - image analysis will fail");
             None
@@ -50,64 +248,88 @@ async fn main() -> Result<()> {
         }
     };
 
-    let handler = JsonRpcHandler::new(api_key);
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    for line in stdin.lock().lines() {
-        match line {
-            Ok(line) => {
-                if line.trim().is_empty() {
-                    continue;
-                }
-
-                match serde_json::from_str::<JsonRpcRequest>(&line) {
-                    Ok(request) => {
-                        let response = handler.handle_request(request).await;
-                        match serde_json::to_string(&response) {
-                            Ok(response_json) => {
-                                if let Err(e) = writeln!(stdout, "{}", response_json) {
-                                    error!("Failed to write response: {}", e);
-                                    break;
-                                }
-                                if let Err(e) = stdout.flush() {
-                                    error!("Failed to flush stdout: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to serialize response: {}", e);
-                                let fallback_error = JsonRpcResponse::error(
-                                    response.id.clone(),
-                                    -32603,
-                                    "Internal error - serialization failed".to_string(),
-                                );
-                                if let Ok(fallback_json) = serde_json::to_string(&fallback_error) {
-                                    let _ = writeln!(stdout, "{}", fallback_json);
-                                    let _ = stdout.flush();
-                                }
-                            }
-                        }
-                    }
-                    Err(parse_error) => {
-                        error!("Failed to parse JSON-RPC request: {}", parse_error);
-                        let error_response = JsonRpcResponse::error(
-                            None,
-                            -32700,
-                            format!("Parse error: {}", parse_error),
-                        );
-                        if let Ok(response_json) = serde_json::to_string(&error_response) {
-                            let _ = writeln!(stdout, "{}", response_json);
-                            let _ = stdout.flush();
-                        }
-                    }
-                }
-            }
-            Err(io_error) => {
-                error!("Failed to read from stdin: {}", io_error);
-                break;
-            }
+    // Same precedence as the API key: command line, then environment variable
+    let api_base_url = match args
+        .api_base_url
+        .or_else(|| std::env::var("GEMINI_API_BASE_URL").ok())
+        .filter(|url| !url.trim().is_empty())
+    {
+        Some(url) => {
+            let url = parse_api_base_url(&url)?;
+            info!("Using Gemini API base URL {}", url);
+            Some(url)
         }
+        None => None,
+    };
+
+    let vertex = match args.vertex_project {
+        Some(project) => {
+            let credentials = args
+                .vertex_credentials
+                .or_else(|| std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS").map(PathBuf::from))
+                .context(
+                    "--vertex-project needs --vertex-credentials or GOOGLE_APPLICATION_CREDENTIALS",
+                )?;
+            let key = ServiceAccountKey::load(&credentials)?;
+            info!(
+                "Using Vertex AI project {} in {} as {}",
+                project,
+                args.vertex_location,
+                key.client_email()
+            );
+            Some(VertexConfig::new(
+                project,
+                args.vertex_location,
+                key,
+                args.vertex_token_url.as_deref(),
+            )?)
+        }
+        None => None,
+    };
+
+    let generation_defaults = GenerationConfig {
+        temperature: args.temperature,
+        top_p: args.top_p,
+        top_k: args.top_k,
+        seed: args.seed,
+        max_output_tokens: args.max_output_tokens,
+    };
+    generation_defaults.validate()?;
+
+    let tools = ToolSelection {
+        enable_tools: args.enable_tools,
+        disable_tools: args.disable_tools,
+        read_only: args.read_only,
+    };
+    let mut handler = JsonRpcHandler::with_config(ServerConfig {
+        api_key,
+        api_base_url,
+        vertex,
+        analysis_model: args.analysis_model,
+        image_model: args.image_model,
+        models_cache_ttl: Duration::from_secs(args.models_cache_ttl),
+        generation_defaults,
+        max_concurrent_requests: args.max_concurrent_requests,
+        include_image: args.include_image,
+        image_max_dimension: args.image_max_dimension,
+        output_dirs: args.output_dirs,
+        prompts_dir: args.prompts_dir,
+        allowed_dirs: args.allowed_dirs,
+        overwrite_policy: args.overwrite_policy,
+        tools: tools.clone(),
+    });
+    if let Some(path) = args.config {
+        let selection = watch_tool_selection(path.clone(), tools)
+            .with_context(|| format!("Failed to load config file {}", path.display()))?;
+        handler = handler.with_tool_selection(selection);
+    }
+    for name in handler.unknown_selected_tools() {
+        warn!("Ignoring unknown tool '{}' in the tool selection", name);
+    }
+
+    match args.transport {
+        Transport::Stdio => stdio_transport::serve(handler).await,
+        Transport::Http => http_transport::serve(handler, args.listen).await?,
     }
 
     info!("Shutting down Gemini Image Analysis MCP Server");
@@ -0,0 +1,325 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Models accepted before the models endpoint has been asked
+pub const KNOWN_MODELS: &[&str] = &[
+    "gemini-2.5-pro",
+    "gemini-2.5-flash",
+    "gemini-2.5-flash-lite",
+    "gemini-2.5-flash-image",
+    "gemini-2.5-flash-image-preview",
+    "gemini-2.0-flash",
+    "gemini-2.0-flash-lite",
+    "gemini-2.0-flash-preview-image-generation",
+];
+
+/// Minimum time between two refreshes of the catalog, so typos don't hammer the models endpoint
+const REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+
+/// How long a `list_models` result is reused unless `--models-cache-ttl` says otherwise
+pub const DEFAULT_MODELS_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Kinds of content a model reads or produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Text,
+    Image,
+}
+
+impl Modality {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Modality::Text => "text",
+            Modality::Image => "image",
+        }
+    }
+}
+
+/// A model as reported by the Gemini `models` endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    /// Model name to pass as `model`, e.g. "gemini-2.5-flash"
+    #[serde(deserialize_with = "deserialize_model_name")]
+    pub name: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    /// API methods the model supports, e.g. "generateContent"
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+    pub input_token_limit: Option<u64>,
+    pub output_token_limit: Option<u64>,
+}
+
+impl ModelInfo {
+    pub fn supports_generate_content(&self) -> bool {
+        self.supported_generation_methods
+            .iter()
+            .any(|method| method == "generateContent")
+    }
+
+    /// The endpoint doesn't report modalities, so they are inferred from the
+    /// model family: Gemini chat models read images, Imagen models only read
+    /// text. Other models that can't `generateContent`, such as embedding
+    /// models, are left empty rather than guessed.
+    pub fn input_modalities(&self) -> Vec<Modality> {
+        if self.name.starts_with("imagen-") {
+            vec![Modality::Text]
+        } else if !self.supports_generate_content() {
+            Vec::new()
+        } else if self.name.starts_with("gemini-") {
+            vec![Modality::Text, Modality::Image]
+        } else {
+            vec![Modality::Text]
+        }
+    }
+
+    /// Inferred like `input_modalities`: image-capable Gemini models carry
+    /// "image" in their name and Imagen models only produce images.
+    pub fn output_modalities(&self) -> Vec<Modality> {
+        if self.name.starts_with("imagen-") {
+            vec![Modality::Image]
+        } else if !self.supports_generate_content() {
+            Vec::new()
+        } else if self.name.starts_with("gemini-") && self.name.contains("image") {
+            vec![Modality::Text, Modality::Image]
+        } else {
+            vec![Modality::Text]
+        }
+    }
+}
+
+/// A model as reported by `list_models`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ModelSummary {
+    /// Name to pass as a tool's `model` argument
+    pub name: String,
+    pub display_name: String,
+    /// Maximum tokens in a request
+    pub input_token_limit: Option<u64>,
+    /// Maximum tokens in a response
+    pub output_token_limit: Option<u64>,
+    /// API methods the model supports, e.g. "generateContent"
+    pub supported_generation_methods: Vec<String>,
+    /// Content the model reads (inferred from the model family; empty when unknown)
+    pub input_modalities: Vec<Modality>,
+    /// Content the model produces (inferred from the model family; empty when unknown)
+    pub output_modalities: Vec<Modality>,
+}
+
+impl From<&ModelInfo> for ModelSummary {
+    fn from(model: &ModelInfo) -> Self {
+        Self {
+            name: model.name.clone(),
+            display_name: model.display_name.clone(),
+            input_token_limit: model.input_token_limit,
+            output_token_limit: model.output_token_limit,
+            supported_generation_methods: model.supported_generation_methods.clone(),
+            input_modalities: model.input_modalities(),
+            output_modalities: model.output_modalities(),
+        }
+    }
+}
+
+fn deserialize_model_name<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Ok(model_name(&name).to_string())
+}
+
+/// Strips the `models/` prefix the API puts on model names.
+pub fn model_name(name: &str) -> &str {
+    name.trim().strip_prefix("models/").unwrap_or(name.trim())
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ListModelsInput {
+    /// Only models supporting this API method, e.g. "generateContent"
+    pub generation_method: Option<String>,
+    /// Only models that read this kind of content
+    pub input_modality: Option<Modality>,
+    /// Only models that produce this kind of content, e.g. "image" for image generation
+    pub output_modality: Option<Modality>,
+}
+
+impl ListModelsInput {
+    pub fn matches(&self, model: &ModelSummary) -> bool {
+        self.generation_method.as_ref().is_none_or(|method| {
+            model
+                .supported_generation_methods
+                .iter()
+                .any(|supported| supported == method)
+        }) && self
+            .input_modality
+            .is_none_or(|modality| model.input_modalities.contains(&modality))
+            && self
+                .output_modality
+                .is_none_or(|modality| model.output_modalities.contains(&modality))
+    }
+}
+
+/// Structured result of `list_models`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ListModelsOutput {
+    pub models: Vec<ModelSummary>,
+}
+
+/// The model names calls are validated against, and the last listing they came from.
+pub struct ModelCatalog {
+    state: Mutex<CatalogState>,
+}
+
+struct CatalogState {
+    names: Vec<String>,
+    refreshed_at: Option<Instant>,
+    listing: Option<(Instant, Arc<Vec<ModelInfo>>)>,
+}
+
+impl ModelCatalog {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(CatalogState {
+                names: KNOWN_MODELS.iter().map(|name| name.to_string()).collect(),
+                refreshed_at: None,
+                listing: None,
+            }),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .names
+            .iter()
+            .any(|known| known == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state.lock().unwrap().names.clone()
+    }
+
+    /// Whether enough time has passed to ask the models endpoint again. Marks the
+    /// refresh as started, so concurrent callers don't all refresh.
+    pub fn begin_refresh(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .refreshed_at
+            .is_some_and(|at| at.elapsed() < REFRESH_INTERVAL)
+        {
+            return false;
+        }
+        state.refreshed_at = Some(Instant::now());
+        true
+    }
+
+    /// The last listing, if it was fetched less than `ttl` ago.
+    pub fn cached_listing(&self, ttl: Duration) -> Option<Arc<Vec<ModelInfo>>> {
+        let state = self.state.lock().unwrap();
+        let (fetched_at, models) = state.listing.as_ref()?;
+        (fetched_at.elapsed() < ttl).then(|| models.clone())
+    }
+
+    /// Stores a fresh listing and replaces the catalog with its models that can generate content.
+    pub fn replace(&self, models: Vec<ModelInfo>) -> Arc<Vec<ModelInfo>> {
+        let names: Vec<String> = models
+            .iter()
+            .filter(|model| model.supports_generate_content())
+            .map(|model| model.name.clone())
+            .collect();
+        let models = Arc::new(models);
+        let mut state = self.state.lock().unwrap();
+        if !names.is_empty() {
+            state.names = names;
+        }
+        state.listing = Some((Instant::now(), models.clone()));
+        models
+    }
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_catalog_is_replaced_by_generate_content_models() {
+        let catalog = ModelCatalog::new();
+        assert!(catalog.contains("gemini-2.5-flash"));
+        assert!(!catalog.contains("gemini-3-pro"));
+
+        let models: Vec<ModelInfo> = serde_json::from_value(json!([
+            { "name": "models/gemini-3-pro", "supportedGenerationMethods": ["generateContent"] },
+            { "name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"] }
+        ]))
+        .unwrap();
+        assert_eq!(models[0].name, "gemini-3-pro");
+        assert!(catalog.cached_listing(DEFAULT_MODELS_CACHE_TTL).is_none());
+        catalog.replace(models);
+
+        assert_eq!(catalog.names(), vec!["gemini-3-pro"]);
+        assert_eq!(
+            catalog
+                .cached_listing(DEFAULT_MODELS_CACHE_TTL)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(catalog.cached_listing(Duration::ZERO).is_none());
+        assert!(catalog.begin_refresh());
+        assert!(!catalog.begin_refresh());
+        assert_eq!(model_name(" models/gemini-2.5-pro"), "gemini-2.5-pro");
+    }
+
+    #[test]
+    fn test_modalities_are_inferred_from_the_model_family() {
+        let model = |name: &str, method: &str| ModelInfo {
+            name: name.to_string(),
+            display_name: String::new(),
+            description: String::new(),
+            supported_generation_methods: vec![method.to_string()],
+            input_token_limit: None,
+            output_token_limit: None,
+        };
+
+        let flash_image = model("gemini-2.5-flash-image-preview", "generateContent");
+        assert_eq!(
+            flash_image.output_modalities(),
+            vec![Modality::Text, Modality::Image]
+        );
+        assert_eq!(
+            flash_image.input_modalities(),
+            vec![Modality::Text, Modality::Image]
+        );
+        assert_eq!(
+            model("gemini-2.5-pro", "generateContent").output_modalities(),
+            vec![Modality::Text]
+        );
+        let imagen = model("imagen-4.0-generate-001", "predict");
+        assert_eq!(imagen.input_modalities(), vec![Modality::Text]);
+        assert_eq!(imagen.output_modalities(), vec![Modality::Image]);
+
+        // Embedding models neither read images nor produce content
+        let embedding = model("gemini-embedding-001", "embedContent");
+        assert!(embedding.input_modalities().is_empty());
+        assert!(embedding.output_modalities().is_empty());
+        let filter = ListModelsInput {
+            input_modality: Some(Modality::Image),
+            ..ListModelsInput::default()
+        };
+        assert!(!filter.matches(&ModelSummary::from(&embedding)));
+        assert!(filter.matches(&ModelSummary::from(&flash_image)));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::error::{McpError, McpResult};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, warn};
+
+/// How long the server waits for the client to answer a server-initiated request
+const CLIENT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Server-initiated requests awaiting a response, keyed by their serialized id
+#[derive(Debug, Default)]
+struct PendingRequests {
+    next_id: AtomicU64,
+    waiting: Mutex<HashMap<String, oneshot::Sender<Value>>>,
+}
+
+/// Channel a peer's messages are handed to the transport through
+#[derive(Debug, Clone)]
+enum Outbox {
+    Unbounded(mpsc::UnboundedSender<String>),
+    /// Messages that don't fit are dropped instead of piling up
+    Bounded(mpsc::Sender<String>),
+}
+
+impl Outbox {
+    /// Queues `message`, or says why it couldn't be.
+    fn send(&self, message: String) -> Result<(), &'static str> {
+        match self {
+            Outbox::Unbounded(sender) => sender.send(message).map_err(|_| "client disconnected"),
+            Outbox::Bounded(sender) => sender.try_send(message).map_err(|e| match e {
+                TrySendError::Full(_) => "client is not reading messages",
+                TrySendError::Closed(_) => "client disconnected",
+            }),
+        }
+    }
+
+    async fn closed(&self) {
+        match self {
+            Outbox::Unbounded(sender) => sender.closed().await,
+            Outbox::Bounded(sender) => sender.closed().await,
+        }
+    }
+}
+
+/// Handle for sending server-initiated messages to the connected client.
+///
+/// Messages are handed to the transport's writer as serialized JSON lines. A
+/// disconnected peer silently drops notifications and fails requests, which is
+/// what tests and transports without a back channel want.
+#[derive(Debug, Clone, Default)]
+pub struct Peer {
+    sender: Option<Outbox>,
+    pending: Arc<PendingRequests>,
+}
+
+impl Peer {
+    pub fn new(sender: mpsc::UnboundedSender<String>) -> Self {
+        Self {
+            sender: Some(Outbox::Unbounded(sender)),
+            pending: Arc::default(),
+        }
+    }
+
+    /// A peer whose messages are dropped while `sender`'s channel is full, for
+    /// transports that may go a long time without reading them.
+    pub fn bounded(sender: mpsc::Sender<String>) -> Self {
+        Self {
+            sender: Some(Outbox::Bounded(sender)),
+            pending: Arc::default(),
+        }
+    }
+
+    pub fn disconnected() -> Self {
+        Self::default()
+    }
+
+    /// Returns a peer that writes to `sender` but shares this peer's outstanding
+    /// requests, so a response arriving on any channel of the session resolves them.
+    pub fn with_sender(&self, sender: mpsc::UnboundedSender<String>) -> Self {
+        Self {
+            sender: Some(Outbox::Unbounded(sender)),
+            pending: Arc::clone(&self.pending),
+        }
+    }
+
+    /// Resolves once the transport stops accepting messages for this client.
+    pub async fn closed(&self) {
+        if let Some(sender) = &self.sender {
+            sender.closed().await;
+        }
+    }
+
+    /// Sends a request to the client and waits for its result.
+    pub async fn request(&self, method: &str, params: Value) -> McpResult<Value> {
+        self.request_with_timeout(method, params, CLIENT_REQUEST_TIMEOUT)
+            .await
+    }
+
+    /// Like `request`, for requests a person has to answer.
+    pub async fn request_with_timeout(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> McpResult<Value> {
+        let Some(sender) = &self.sender else {
+            return Err(McpError::ClientRequestError(format!(
+                "cannot send '{}': no client connected",
+                method
+            )));
+        };
+
+        let id = self.pending.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let key = json!(id).to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending.waiting.lock().unwrap().insert(key.clone(), tx);
+
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        if let Err(reason) = sender.send(request.to_string()) {
+            self.pending.waiting.lock().unwrap().remove(&key);
+            return Err(McpError::ClientRequestError(format!(
+                "cannot send '{}': {}",
+                method, reason
+            )));
+        }
+
+        let response = match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => {
+                return Err(McpError::ClientRequestError(format!(
+                    "'{}' was abandoned",
+                    method
+                )));
+            }
+            Err(_) => {
+                self.pending.waiting.lock().unwrap().remove(&key);
+                return Err(McpError::Timeout(format!(
+                    "client did not answer '{}' within {}s",
+                    method,
+                    timeout.as_secs()
+                )));
+            }
+        };
+
+        match response.get("error") {
+            Some(error) => Err(McpError::ClientRequestError(format!(
+                "'{}' failed: {}",
+                method,
+                error
+                    .get("message")
+                    .and_then(|message| message.as_str())
+                    .unwrap_or("unknown error")
+            ))),
+            None => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Resolves the request a client response belongs to. Returns false when no
+    /// request with that id is outstanding.
+    pub fn handle_response(&self, response: Value) -> bool {
+        let key = response.get("id").map(Value::to_string).unwrap_or_default();
+        let Some(waiter) = self.pending.waiting.lock().unwrap().remove(&key) else {
+            warn!("Ignoring response to unknown request {}", key);
+            return false;
+        };
+        let _ = waiter.send(response);
+        true
+    }
+
+    pub fn notify(&self, method: &str, params: Value) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        if let Err(reason) = sender.send(notification.to_string()) {
+            debug!("Dropping '{}' notification: {}", method, reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_sends_json_rpc_notification() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let peer = Peer::new(tx);
+
+        peer.notify("notifications/test", json!({ "value": 1 }));
+
+        let message: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["jsonrpc"], "2.0");
+        assert_eq!(message["method"], "notifications/test");
+        assert_eq!(message["params"]["value"], 1);
+        assert!(message.get("id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bounded_peer_drops_messages_while_full() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let peer = Peer::bounded(tx);
+
+        peer.notify("notifications/first", json!({}));
+        peer.notify("notifications/second", json!({}));
+        let error = peer.request("roots/list", json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("not reading"));
+
+        let message: Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(message["method"], "notifications/first");
+        assert!(rx.try_recv().is_err());
+
+        // Room frees up once the transport reads
+        peer.notify("notifications/third", json!({}));
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_peer_drops_messages() {
+        let peer = Peer::disconnected();
+        peer.notify("notifications/test", json!({}));
+        assert!(peer.request("roots/list", json!({})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_resolves_with_client_response() {
+        let (session_tx, _session_rx) = mpsc::unbounded_channel();
+        let (request_tx, mut request_rx) = mpsc::unbounded_channel();
+        let session = Peer::new(session_tx);
+        let peer = session.with_sender(request_tx);
+
+        let client = tokio::spawn(async move {
+            let request: Value = serde_json::from_str(&request_rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["method"], "roots/list");
+            // The response may arrive through any channel of the session
+            assert!(session.handle_response(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "roots": [] }
+            })));
+            assert!(!session.handle_response(json!({ "id": 999, "result": {} })));
+        });
+
+        let result = peer.request("roots/list", json!({})).await.unwrap();
+        assert_eq!(result, json!({ "roots": [] }));
+        client.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_error_response() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let peer = Peer::new(tx);
+        let responder = peer.clone();
+
+        tokio::spawn(async move {
+            let request: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            responder.handle_response(json!({
+                "id": request["id"],
+                "error": { "code": -32601, "message": "Method not found" }
+            }));
+        });
+
+        let error = peer.request("roots/list", json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("Method not found"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::peer::Peer;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+
+/// Stages a Gemini tool call goes through, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressStage {
+    Validating,
+    FetchingInputs,
+    Uploading,
+    WaitingForModel,
+    Decoding,
+    WritingOutput,
+}
+
+impl ProgressStage {
+    fn index(self) -> f64 {
+        match self {
+            ProgressStage::Validating => 0.0,
+            ProgressStage::FetchingInputs => 1.0,
+            ProgressStage::Uploading => 2.0,
+            ProgressStage::WaitingForModel => 3.0,
+            ProgressStage::Decoding => 4.0,
+            ProgressStage::WritingOutput => 5.0,
+        }
+    }
+}
+
+/// Reported once the last stage is done, so a finished call ends at `progress == total`
+const TOTAL_STAGES: f64 = 6.0;
+
+/// Emits `notifications/progress` for a request that supplied `_meta.progressToken`.
+///
+/// Reporters without a token are no-ops, so Gemini client code can report
+/// progress unconditionally.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    token: Option<Value>,
+    peer: Peer,
+    last_progress: Arc<Mutex<f64>>,
+}
+
+impl ProgressReporter {
+    pub fn new(token: Value, peer: Peer) -> Self {
+        Self {
+            token: Some(token),
+            peer,
+            last_progress: Arc::new(Mutex::new(-1.0)),
+        }
+    }
+
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn stage(&self, stage: ProgressStage, message: impl Into<String>) {
+        self.send(stage.index(), message.into());
+    }
+
+    /// Reports that the call has completed every stage.
+    pub fn finished(&self, message: impl Into<String>) {
+        self.send(TOTAL_STAGES, message.into());
+    }
+
+    /// Reports fetching input image `index` (zero-based) of `count`.
+    pub fn fetching_image(&self, index: usize, count: usize, source: &str) {
+        let fraction = index as f64 / count.max(1) as f64;
+        self.send(
+            ProgressStage::FetchingInputs.index() + fraction,
+            format!("Fetching image {} of {}: {}", index + 1, count, source),
+        );
+    }
+
+    /// Reports that the model is still working after `elapsed_secs`; the progress
+    /// value creeps towards the next stage without ever reaching it.
+    pub fn waiting_for_model(&self, tick: u32, elapsed_secs: u64) {
+        let fraction = f64::from(tick) / f64::from(tick + 1);
+        self.send(
+            ProgressStage::WaitingForModel.index() + fraction,
+            format!("Waiting for the model ({}s elapsed)", elapsed_secs),
+        );
+    }
+
+    fn send(&self, progress: f64, message: String) {
+        let Some(token) = &self.token else {
+            return;
+        };
+
+        // Progress must increase with every notification
+        {
+            let mut last_progress = self.last_progress.lock().unwrap();
+            if progress <= *last_progress {
+                return;
+            }
+            *last_progress = progress;
+        }
+
+        self.peer.notify(
+            "notifications/progress",
+            json!({
+                "progressToken": token,
+                "progress": progress,
+                "total": TOTAL_STAGES,
+                "message": message,
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn received(rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<Value> {
+        let mut messages = vec![];
+        while let Ok(message) = rx.try_recv() {
+            messages.push(serde_json::from_str::<Value>(&message).unwrap()["params"].clone());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_progress_reports_stages_in_order() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let progress = ProgressReporter::new(json!("token-1"), Peer::new(tx));
+
+        progress.stage(ProgressStage::Validating, "Validating input");
+        progress.fetching_image(0, 2, "a.png");
+        progress.fetching_image(1, 2, "b.png");
+        progress.stage(ProgressStage::Uploading, "Uploading");
+        progress.waiting_for_model(1, 5);
+        progress.stage(ProgressStage::WritingOutput, "Writing");
+        progress.finished("Done");
+
+        let messages = received(&mut rx);
+        assert_eq!(messages.len(), 7);
+        assert!(messages.iter().all(|m| m["progressToken"] == "token-1"));
+        assert!(messages.iter().all(|m| m["total"] == 6.0));
+        assert_eq!(messages[2]["progress"], 1.5);
+        assert_eq!(messages[2]["message"], "Fetching image 2 of 2: b.png");
+        assert_eq!(messages[4]["progress"], 3.5);
+        assert_eq!(messages[6]["progress"], messages[6]["total"]);
+    }
+
+    #[test]
+    fn test_progress_never_goes_backwards() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let progress = ProgressReporter::new(json!(1), Peer::new(tx));
+
+        progress.stage(ProgressStage::Decoding, "Decoding");
+        progress.stage(ProgressStage::Uploading, "Uploading");
+        progress.stage(ProgressStage::Decoding, "Decoding again");
+
+        assert_eq!(received(&mut rx).len(), 1);
+    }
+
+    #[test]
+    fn test_progress_without_token_sends_nothing() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let progress = ProgressReporter {
+            peer: Peer::new(tx),
+            ..ProgressReporter::none()
+        };
+
+        progress.stage(ProgressStage::Validating, "Validating input");
+
+        assert!(received(&mut rx).is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+use crate::error::{McpError, McpResult};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::path::Path;
+
+/// A parameterized prompt served through `prompts/list` and `prompts/get`.
+///
+/// Templates reference arguments as `{{name}}`. In a prompts directory they are
+/// written either as a `.toml` file with a `template` key, or as a `.md` file
+/// whose body is the template, optionally preceded by TOML front matter between
+/// `+++` lines. The file stem is used when no `name` is given.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptTemplate {
+    #[serde(default)]
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    #[serde(default)]
+    pub template: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    /// Suggested values offered through `completion/complete`
+    #[serde(default)]
+    pub presets: Vec<String>,
+}
+
+impl PromptTemplate {
+    fn descriptor(&self) -> Value {
+        let arguments: Vec<Value> = self
+            .arguments
+            .iter()
+            .map(|argument| {
+                json!({
+                    "name": argument.name,
+                    "description": argument.description,
+                    "required": argument.required,
+                })
+            })
+            .collect();
+        json!({
+            "name": self.name,
+            "title": self.title,
+            "description": self.description,
+            "arguments": arguments,
+        })
+    }
+
+    /// Substitutes `arguments` into the template. Missing optional arguments
+    /// become empty strings; missing required ones are an error.
+    fn render(&self, arguments: &Map<String, Value>) -> McpResult<String> {
+        let mut text = self.template.clone();
+        for argument in &self.arguments {
+            let value = match arguments.get(&argument.name) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Null) | None if argument.required => {
+                    return Err(McpError::InvalidInput(format!(
+                        "Missing required argument '{}' for prompt '{}'",
+                        argument.name, self.name
+                    )));
+                }
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            };
+            text = text.replace(&format!("{{{{{}}}}}", argument.name), &value);
+        }
+        Ok(text.trim().to_string())
+    }
+}
+
+/// Built-in prompts plus any loaded from a prompts directory.
+#[derive(Debug, Clone)]
+pub struct PromptLibrary {
+    prompts: Vec<PromptTemplate>,
+}
+
+impl Default for PromptLibrary {
+    fn default() -> Self {
+        Self {
+            prompts: builtin_prompts(),
+        }
+    }
+}
+
+impl PromptLibrary {
+    /// Loads `*.toml` and `*.md` prompts from `dir` on top of the built-ins; a
+    /// file prompt replaces a built-in one with the same name.
+    pub fn load(dir: &Path) -> McpResult<Self> {
+        let mut library = Self::default();
+        let mut paths: Vec<_> = std::fs::read_dir(dir)
+            .map_err(|e| {
+                McpError::ConfigurationError(format!(
+                    "Cannot read prompts directory {}: {}",
+                    dir.display(),
+                    e
+                ))
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let prompt = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("toml") => parse_toml_prompt(&path)?,
+                Some("md") => parse_markdown_prompt(&path)?,
+                _ => continue,
+            };
+            library
+                .prompts
+                .retain(|existing| existing.name != prompt.name);
+            library.prompts.push(prompt);
+        }
+
+        Ok(library)
+    }
+
+    pub fn list(&self) -> Vec<Value> {
+        self.prompts
+            .iter()
+            .map(PromptTemplate::descriptor)
+            .collect()
+    }
+
+    /// Presets of a prompt argument that start with `prefix`, ignoring case.
+    pub fn presets(&self, prompt: &str, argument: &str, prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        self.prompts
+            .iter()
+            .find(|candidate| candidate.name == prompt)
+            .and_then(|prompt| prompt.arguments.iter().find(|arg| arg.name == argument))
+            .map(|argument| {
+                argument
+                    .presets
+                    .iter()
+                    .filter(|preset| preset.to_lowercase().starts_with(&prefix))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Renders the named prompt into a `prompts/get` result, or `None` if no
+    /// prompt has that name.
+    pub fn get(&self, name: &str, arguments: &Map<String, Value>) -> Option<McpResult<Value>> {
+        let prompt = self.prompts.iter().find(|prompt| prompt.name == name)?;
+        Some(prompt.render(arguments).map(|text| {
+            json!({
+                "description": prompt.description,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": { "type": "text", "text": text }
+                    }
+                ]
+            })
+        }))
+    }
+}
+
+fn parse_toml_prompt(path: &Path) -> McpResult<PromptTemplate> {
+    let text = read_prompt_file(path)?;
+    let prompt = toml::from_str::<PromptTemplate>(&text).map_err(|e| invalid_prompt(path, e))?;
+    finish_prompt(path, prompt)
+}
+
+fn parse_markdown_prompt(path: &Path) -> McpResult<PromptTemplate> {
+    let text = read_prompt_file(path)?;
+    let (front_matter, body) = match text.strip_prefix("+++") {
+        Some(rest) => rest
+            .split_once("\n+++")
+            .ok_or_else(|| invalid_prompt(path, "unterminated +++ front matter"))?,
+        None => ("", text.as_str()),
+    };
+
+    let mut prompt =
+        toml::from_str::<PromptTemplate>(front_matter).map_err(|e| invalid_prompt(path, e))?;
+    prompt.template = body.to_string();
+    finish_prompt(path, prompt)
+}
+
+fn finish_prompt(path: &Path, mut prompt: PromptTemplate) -> McpResult<PromptTemplate> {
+    if prompt.name.is_empty() {
+        prompt.name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    if prompt.template.trim().is_empty() {
+        return Err(invalid_prompt(path, "template is empty"));
+    }
+    Ok(prompt)
+}
+
+fn read_prompt_file(path: &Path) -> McpResult<String> {
+    std::fs::read_to_string(path).map_err(|e| {
+        McpError::ConfigurationError(format!("Cannot read prompt {}: {}", path.display(), e))
+    })
+}
+
+fn invalid_prompt(path: &Path, reason: impl std::fmt::Display) -> McpError {
+    McpError::ConfigurationError(format!("Invalid prompt {}: {}", path.display(), reason))
+}
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.to_string(),
+        description: Some(description.to_string()),
+        required,
+        presets: vec![],
+    }
+}
+
+fn argument_with_presets(name: &str, description: &str, presets: &[&str]) -> PromptArgument {
+    PromptArgument {
+        presets: presets.iter().map(|preset| preset.to_string()).collect(),
+        ..argument(name, description, false)
+    }
+}
+
+fn builtin_prompts() -> Vec<PromptTemplate> {
+    vec![
+        PromptTemplate {
+            name: "alt_text".to_string(),
+            title: Some("Alt Text".to_string()),
+            description: Some("Write accessible alt text for an image".to_string()),
+            arguments: vec![
+                argument("image_source", "Image URL or local file path", true),
+                argument_with_presets(
+                    "context",
+                    "Where the image appears, e.g. a product page",
+                    &[
+                        "product page",
+                        "blog post",
+                        "social media post",
+                        "documentation",
+                    ],
+                ),
+            ],
+            template: "Use the analyze_image tool on {{image_source}} and write alt text for it. \
+                Keep it under 125 characters, describe what matters for someone who cannot see \
+                the image, and do not start with \"Image of\". Context: {{context}}"
+                .to_string(),
+        },
+        PromptTemplate {
+            name: "product_photo".to_string(),
+            title: Some("Product Photo".to_string()),
+            description: Some("Turn a product snapshot into a clean catalog photo".to_string()),
+            arguments: vec![
+                argument("image_source", "Product image URL or local file path", true),
+                argument("output_path", "Where to save the edited photo", true),
+                argument_with_presets(
+                    "background",
+                    "Background to use, e.g. plain white",
+                    &[
+                        "plain white",
+                        "light gray",
+                        "transparent",
+                        "lifestyle setting",
+                    ],
+                ),
+            ],
+            template: "Use the edit_image tool on {{image_source}} and save the result to \
+                {{output_path}}. Isolate the product, place it on this background: \
+                {{background}} (plain white if empty), even out the lighting, remove reflections \
+                and clutter, and keep the product's shape, colors and labels unchanged."
+                .to_string(),
+        },
+        PromptTemplate {
+            name: "ui_review".to_string(),
+            title: Some("UI Review".to_string()),
+            description: Some("Review a UI screenshot for usability and accessibility".to_string()),
+            arguments: vec![
+                argument("image_source", "Screenshot URL or local file path", true),
+                argument_with_presets(
+                    "focus",
+                    "Aspect to focus on, e.g. forms or navigation",
+                    &[
+                        "accessibility",
+                        "forms",
+                        "navigation",
+                        "typography",
+                        "mobile layout",
+                    ],
+                ),
+            ],
+            template: "Use the analyze_image tool on {{image_source}} and review the user \
+                interface. List concrete problems with layout, visual hierarchy, contrast, \
+                text legibility and touch target sizes, most severe first, each with a suggested \
+                fix. Pay particular attention to: {{focus}}"
+                .to_string(),
+        },
+        PromptTemplate {
+            name: "house_style".to_string(),
+            title: Some("Apply House Style".to_string()),
+            description: Some("Restyle an image to match a reference image".to_string()),
+            arguments: vec![
+                argument("source_image", "Image to restyle", true),
+                argument("style_image", "Reference image in the house style", true),
+                argument("output_path", "Where to save the restyled image", true),
+            ],
+            template: "Use the style_transfer tool with source_image {{source_image}} and \
+                style_image {{style_image}}, saving to {{output_path}}. Match the reference's \
+                palette, line work and texture while keeping the composition and subject of the \
+                source image."
+                .to_string(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_builtin_prompt_renders_arguments() {
+        let library = PromptLibrary::default();
+        assert!(
+            library
+                .list()
+                .iter()
+                .any(|prompt| prompt["name"] == "alt_text")
+        );
+
+        let result = library
+            .get(
+                "alt_text",
+                &arguments(json!({ "image_source": "./cat.png" })),
+            )
+            .unwrap()
+            .unwrap();
+        let text = result["messages"][0]["content"]["text"].as_str().unwrap();
+        assert!(text.contains("analyze_image tool on ./cat.png"));
+        assert!(!text.contains("{{"));
+
+        assert!(library.get("missing", &Map::new()).is_none());
+        assert!(matches!(
+            library.get("alt_text", &Map::new()),
+            Some(Err(McpError::InvalidInput(_)))
+        ));
+    }
+
+    #[test]
+    fn test_load_prompts_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("brand.toml"),
+            r#"
+title = "Brand Colors"
+description = "Recolor with brand colors"
+template = "Use edit_image on {{image_source}} with colors {{colors}}"
+
+[[arguments]]
+name = "image_source"
+required = true
+
+[[arguments]]
+name = "colors"
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("alt_text.md"),
+            "+++\ndescription = \"House alt text\"\n[[arguments]]\nname = \"image_source\"\nrequired = true\n+++\nDescribe {{image_source}} in our voice.\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README.txt"), "ignored").unwrap();
+
+        let library = PromptLibrary::load(dir.path()).unwrap();
+
+        let brand = library
+            .get(
+                "brand",
+                &arguments(json!({ "image_source": "a.png", "colors": "teal" })),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            brand["messages"][0]["content"]["text"],
+            "Use edit_image on a.png with colors teal"
+        );
+
+        let alt_text = library
+            .get("alt_text", &arguments(json!({ "image_source": "b.png" })))
+            .unwrap()
+            .unwrap();
+        assert_eq!(alt_text["description"], "House alt text");
+        assert_eq!(
+            alt_text["messages"][0]["content"]["text"],
+            "Describe b.png in our voice."
+        );
+        assert_eq!(
+            library
+                .list()
+                .iter()
+                .filter(|prompt| prompt["name"] == "alt_text")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_presets_filtered_by_prefix() {
+        let library = PromptLibrary::default();
+
+        assert_eq!(
+            library.presets("product_photo", "background", "L"),
+            vec!["light gray", "lifestyle setting"]
+        );
+        assert!(
+            library
+                .presets("product_photo", "output_path", "")
+                .is_empty()
+        );
+        assert!(library.presets("missing", "background", "").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_prompt_file_is_a_configuration_error() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("broken.toml"), "template = ").unwrap();
+
+        assert!(matches!(
+            PromptLibrary::load(dir.path()),
+            Err(McpError::ConfigurationError(_))
+        ));
+    }
+}
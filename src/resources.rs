@@ -0,0 +1,251 @@
+use crate::error::{McpError, McpResult};
+use crate::gemini_client::GeneratedImage;
+use crate::validation::{get_mime_type_from_extension, has_image_extension};
+use base64::{Engine as _, engine::general_purpose};
+use reqwest::Url;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::fs;
+
+/// URI template under which images generated in a session are exposed
+pub const GENERATION_URI_TEMPLATE: &str = "gemini-image://generations/{id}";
+const GENERATION_URI_PREFIX: &str = "gemini-image://generations/";
+
+#[derive(Debug, Clone)]
+struct Generation {
+    id: String,
+    file_path: PathBuf,
+    mime_type: String,
+}
+
+impl Generation {
+    fn uri(&self) -> String {
+        format!("{}{}", GENERATION_URI_PREFIX, self.id)
+    }
+}
+
+/// Images written by the generator tools during one client session.
+#[derive(Debug, Default)]
+pub struct GenerationRegistry {
+    generations: Mutex<Vec<Generation>>,
+}
+
+impl GenerationRegistry {
+    /// Records a saved image and returns its resource URI.
+    pub fn record(&self, image: &GeneratedImage) -> String {
+        let generation = Generation {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            file_path: PathBuf::from(&image.file_path),
+            mime_type: image.mime_type.clone(),
+        };
+        let uri = generation.uri();
+        self.generations.lock().unwrap().push(generation);
+        uri
+    }
+
+    fn get(&self, id: &str) -> Option<Generation> {
+        self.generations
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|generation| generation.id == id)
+            .cloned()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.generations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|generation| generation.id.clone())
+            .collect()
+    }
+
+    fn all(&self) -> Vec<Generation> {
+        self.generations.lock().unwrap().clone()
+    }
+}
+
+/// Lists this session's generations followed by the images in `output_dirs`.
+pub async fn list_resources(registry: &GenerationRegistry, output_dirs: &[PathBuf]) -> Vec<Value> {
+    let mut resources = vec![];
+
+    for generation in registry.all() {
+        // Skip images that were deleted or moved since they were generated
+        let Ok(metadata) = fs::metadata(&generation.file_path).await else {
+            continue;
+        };
+        resources.push(json!({
+            "uri": generation.uri(),
+            "name": file_name(&generation.file_path),
+            "description": format!("Generated image saved to {}", generation.file_path.display()),
+            "mimeType": generation.mime_type,
+            "size": metadata.len(),
+        }));
+    }
+
+    for dir in output_dirs {
+        let Ok(mut entries) = fs::read_dir(dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if !metadata.is_file() || !has_image_extension(&path.to_string_lossy()) {
+                continue;
+            }
+            let Some(uri) = file_uri(&path).await else {
+                continue;
+            };
+            resources.push(json!({
+                "uri": uri,
+                "name": file_name(&path),
+                "mimeType": get_mime_type_from_extension(&path.to_string_lossy()),
+                "size": metadata.len(),
+            }));
+        }
+    }
+
+    resources
+}
+
+/// Reads a resource as a base64 blob. Returns `None` for URIs that don't name a
+/// generation of this session or an image inside one of `output_dirs`.
+pub async fn read_resource(
+    registry: &GenerationRegistry,
+    output_dirs: &[PathBuf],
+    uri: &str,
+) -> McpResult<Option<Value>> {
+    let (path, mime_type) = if let Some(id) = uri.strip_prefix(GENERATION_URI_PREFIX) {
+        match registry.get(id) {
+            Some(generation) => (generation.file_path, generation.mime_type),
+            None => return Ok(None),
+        }
+    } else {
+        match output_dir_file(output_dirs, uri).await {
+            Some(path) => {
+                let mime_type = get_mime_type_from_extension(&path.to_string_lossy());
+                (path, mime_type)
+            }
+            None => return Ok(None),
+        }
+    };
+
+    let bytes = fs::read(&path)
+        .await
+        .map_err(|e| McpError::FileSystemError(format!("Cannot read {}: {}", path.display(), e)))?;
+
+    Ok(Some(json!({
+        "uri": uri,
+        "mimeType": mime_type,
+        "blob": general_purpose::STANDARD.encode(&bytes),
+    })))
+}
+
+/// Resolves a `file://` URI to an image inside one of the output directories.
+async fn output_dir_file(output_dirs: &[PathBuf], uri: &str) -> Option<PathBuf> {
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    let path = fs::canonicalize(path).await.ok()?;
+    if !has_image_extension(&path.to_string_lossy()) {
+        return None;
+    }
+
+    for dir in output_dirs {
+        if let Ok(dir) = fs::canonicalize(dir).await
+            && path.parent() == Some(dir.as_path())
+        {
+            return Some(path);
+        }
+    }
+    None
+}
+
+async fn file_uri(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).await.ok()?;
+    Url::from_file_path(path).ok().map(String::from)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generated(path: &Path) -> GeneratedImage {
+        GeneratedImage {
+            file_path: path.to_string_lossy().into_owned(),
+            mime_type: "image/png".to_string(),
+            data: vec![],
+            model: "test-model".to_string(),
+            generation_config: None,
+            usage: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generations_are_listed_and_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cat.png");
+        std::fs::write(&path, [1, 2, 3]).unwrap();
+        let registry = GenerationRegistry::default();
+
+        let uri = registry.record(&generated(&path));
+
+        let resources = list_resources(&registry, &[]).await;
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0]["uri"], uri);
+        assert_eq!(resources[0]["name"], "cat.png");
+        assert_eq!(resources[0]["size"], 3);
+
+        let contents = read_resource(&registry, &[], &uri).await.unwrap().unwrap();
+        assert_eq!(contents["mimeType"], "image/png");
+        assert_eq!(contents["blob"], "AQID");
+
+        let unknown = format!("{}missing", GENERATION_URI_PREFIX);
+        assert!(
+            read_resource(&registry, &[], &unknown)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_dir_images_are_listed_and_confined() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.jpg"), [0xff]).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "x").unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.png"), [0]).unwrap();
+        let registry = GenerationRegistry::default();
+        let output_dirs = vec![dir.path().to_path_buf()];
+
+        let resources = list_resources(&registry, &output_dirs).await;
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0]["mimeType"], "image/jpeg");
+
+        let uri = resources[0]["uri"].as_str().unwrap();
+        assert!(uri.starts_with("file://"));
+        assert!(
+            read_resource(&registry, &output_dirs, uri)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        let outside_uri = file_uri(&outside.path().join("secret.png")).await.unwrap();
+        assert!(
+            read_resource(&registry, &output_dirs, &outside_uri)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use crate::error::{McpError, McpResult};
+use crate::peer::Peer;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::{info, warn};
+
+/// How long the user has to answer an overwrite confirmation
+const ELICITATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What a generator tool does when its output file already exists.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    /// Ask the user through MCP elicitation; overwrite when the client can't ask
+    #[default]
+    Ask,
+    /// Replace the existing file
+    Overwrite,
+    /// Save next to it under the first free name (`cat-1.png`, `cat-2.png`, ...)
+    Rename,
+    /// Fail the tool call
+    Fail,
+}
+
+/// Where a generated image is written, as resolved by `FileAccess::prepare_output`.
+///
+/// A name picked by the rename policy is reserved with an empty placeholder file,
+/// so concurrent saves can't pick it too. The placeholder is removed again on
+/// drop unless an image has been written over it.
+#[derive(Debug)]
+pub struct OutputPath {
+    path: PathBuf,
+    placeholder: bool,
+}
+
+impl OutputPath {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for OutputPath {
+    fn drop(&mut self) {
+        if self.placeholder
+            && std::fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() == 0)
+        {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Directories the tools may read input images from and write generated images to.
+///
+/// Paths are canonicalized before they are compared, so symlinks and `..`
+/// segments cannot step outside a root.
+#[derive(Debug, Clone)]
+pub struct FileAccess {
+    roots: Vec<PathBuf>,
+    /// Applied when a call doesn't pick an overwrite policy
+    overwrite_policy: OverwritePolicy,
+    /// Peer that can show `elicitation/create` requests to the user
+    elicitor: Option<Peer>,
+}
+
+impl FileAccess {
+    /// Canonicalizes `roots`, dropping the ones that don't exist.
+    pub async fn new(roots: &[PathBuf]) -> Self {
+        let mut canonical = vec![];
+        for root in roots {
+            match fs::canonicalize(root).await {
+                Ok(root) => canonical.push(root),
+                Err(e) => warn!("Ignoring root '{}': {}", root.display(), e),
+            }
+        }
+        Self {
+            roots: canonical,
+            overwrite_policy: OverwritePolicy::default(),
+            elicitor: None,
+        }
+    }
+
+    /// Sets the default overwrite policy and, for clients that support
+    /// elicitation, the peer used to ask the user.
+    pub fn with_overwrite(mut self, policy: OverwritePolicy, elicitor: Option<Peer>) -> Self {
+        self.overwrite_policy = policy;
+        self.elicitor = elicitor;
+        self
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Resolves an existing input file, failing when it lies outside every root.
+    pub async fn check_read(&self, path: &str) -> McpResult<PathBuf> {
+        let resolved = fs::canonicalize(path)
+            .await
+            .map_err(|_| McpError::FileSystemError(format!("File not found: {}", path)))?;
+        self.confine(path, resolved)
+    }
+
+    /// Resolves the output file `path`, failing when its directory lies outside
+    /// every root. The file itself doesn't have to exist yet.
+    pub async fn check_write(&self, path: &str) -> McpResult<PathBuf> {
+        let path_ref = Path::new(path);
+        let file_name = path_ref.file_name().ok_or_else(|| {
+            McpError::InvalidInput(format!("Output path has no file name: {}", path))
+        })?;
+        let parent = match path_ref.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let resolved = fs::canonicalize(parent).await.map_err(|_| {
+            McpError::FileSystemError(format!(
+                "Output directory does not exist: {}",
+                parent.display()
+            ))
+        })?;
+        self.confine(path, resolved.join(file_name))
+    }
+
+    /// Resolves where a generated image goes, applying `policy` (or the default)
+    /// when `path` already exists.
+    pub async fn prepare_output(
+        &self,
+        path: &str,
+        policy: Option<OverwritePolicy>,
+    ) -> McpResult<OutputPath> {
+        let resolved = self.check_write(path).await?;
+        if !fs::try_exists(&resolved).await.unwrap_or(false) {
+            return Ok(OutputPath {
+                path: resolved,
+                placeholder: false,
+            });
+        }
+
+        let policy = match (policy.unwrap_or(self.overwrite_policy), &self.elicitor) {
+            (OverwritePolicy::Ask, Some(elicitor)) => {
+                ask_overwrite(elicitor, path, &free_path(&resolved).await).await?
+            }
+            (OverwritePolicy::Ask, None) => OverwritePolicy::Overwrite,
+            (policy, _) => policy,
+        };
+
+        match policy {
+            OverwritePolicy::Overwrite | OverwritePolicy::Ask => {
+                info!("Overwriting existing file '{}'", path);
+                Ok(OutputPath {
+                    path: resolved,
+                    placeholder: false,
+                })
+            }
+            OverwritePolicy::Rename => Ok(OutputPath {
+                path: reserve_free_path(&resolved).await?,
+                placeholder: true,
+            }),
+            OverwritePolicy::Fail => Err(McpError::InvalidInput(format!(
+                "'{}' already exists; choose another output_path or set overwrite_policy to \"overwrite\" or \"rename\"",
+                path
+            ))),
+        }
+    }
+
+    fn confine(&self, path: &str, resolved: PathBuf) -> McpResult<PathBuf> {
+        if self.roots.iter().any(|root| resolved.starts_with(root)) {
+            return Ok(resolved);
+        }
+
+        let allowed: Vec<String> = self
+            .roots
+            .iter()
+            .map(|root| root.display().to_string())
+            .collect();
+        Err(McpError::InvalidInput(if allowed.is_empty() {
+            format!(
+                "Access to '{}' denied: the client has not shared any roots",
+                path
+            )
+        } else {
+            format!(
+                "Access to '{}' denied: path is outside the allowed roots ({})",
+                path,
+                allowed.join(", ")
+            )
+        }))
+    }
+}
+
+/// Asks the user whether to overwrite `path` or save to `renamed` instead.
+/// Declining or cancelling the form fails the tool call with `OverwriteDeclined`.
+async fn ask_overwrite(elicitor: &Peer, path: &str, renamed: &Path) -> McpResult<OverwritePolicy> {
+    let renamed_name = renamed
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let params = json!({
+        "message": format!("'{}' already exists. Overwrite it, save the new image as '{}', or cancel?", path, renamed_name),
+        "requestedSchema": {
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "title": "Existing file",
+                    "enum": ["overwrite", "rename", "cancel"],
+                    "enumNames": ["Overwrite", format!("Save as {}", renamed_name), "Cancel"]
+                }
+            },
+            "required": ["action"]
+        }
+    });
+
+    let result = elicitor
+        .request_with_timeout("elicitation/create", params, ELICITATION_TIMEOUT)
+        .await?;
+    match (
+        result["action"].as_str(),
+        result.pointer("/content/action").and_then(Value::as_str),
+    ) {
+        (Some("accept"), Some("overwrite")) => Ok(OverwritePolicy::Overwrite),
+        (Some("accept"), Some("rename")) => Ok(OverwritePolicy::Rename),
+        _ => Err(McpError::OverwriteDeclined(path.to_string())),
+    }
+}
+
+/// `stem-1.ext`, `stem-2.ext`, ... next to `path`.
+fn renamed_paths(path: &Path) -> impl Iterator<Item = PathBuf> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let path = path.to_path_buf();
+    (1..).map(move |counter| path.with_file_name(format!("{}-{}{}", stem, counter, extension)))
+}
+
+/// First renamed path that doesn't exist, for showing the user.
+async fn free_path(path: &Path) -> PathBuf {
+    for candidate in renamed_paths(path) {
+        if !fs::try_exists(&candidate).await.unwrap_or(true) {
+            return candidate;
+        }
+    }
+    unreachable!("renamed paths never run out")
+}
+
+/// First renamed path that doesn't exist, created empty in the same step so no
+/// other save can take it.
+async fn reserve_free_path(path: &Path) -> McpResult<PathBuf> {
+    for candidate in renamed_paths(path) {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+            .await
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(McpError::FileSystemError(format!(
+                    "Failed to reserve '{}': {}",
+                    candidate.display(),
+                    e
+                )));
+            }
+        }
+    }
+    unreachable!("renamed paths never run out")
+}
+
+/// Extracts the directories of a `roots/list` result. Roots that are not
+/// `file://` URIs are skipped.
+pub fn roots_from_list(result: &Value) -> Vec<PathBuf> {
+    result["roots"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|root| root["uri"].as_str())
+        .filter_map(|uri| Url::parse(uri).ok()?.to_file_path().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_paths_are_confined_to_roots() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("in")).unwrap();
+        std::fs::write(root.path().join("in/cat.png"), [0]).unwrap();
+        std::fs::write(outside.path().join("secret.png"), [0]).unwrap();
+        let files = FileAccess::new(&[root.path().join("in")]).await;
+
+        let inside = root.path().join("in/cat.png");
+        assert!(files.check_read(inside.to_str().unwrap()).await.is_ok());
+        // Both temp dirs share a parent, so `..` walks from one into the other
+        let escape = root
+            .path()
+            .join("in/../..")
+            .join(outside.path().file_name().unwrap())
+            .join("secret.png");
+        assert!(files.check_read(escape.to_str().unwrap()).await.is_err());
+
+        let output = root.path().join("in/new.png");
+        assert_eq!(
+            files.check_write(output.to_str().unwrap()).await.unwrap(),
+            files.roots()[0].join("new.png")
+        );
+        let output = outside.path().join("new.png");
+        let error = files
+            .check_write(output.to_str().unwrap())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("outside the allowed roots"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_out_of_a_root_are_rejected() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.png"), [0]).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+        let files = FileAccess::new(&[root.path().to_path_buf()]).await;
+
+        let linked = root.path().join("link/secret.png");
+        assert!(files.check_read(linked.to_str().unwrap()).await.is_err());
+        let linked = root.path().join("link/out.png");
+        assert!(files.check_write(linked.to_str().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prepare_output_applies_overwrite_policy() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cat.png"), [0]).unwrap();
+        std::fs::write(root.path().join("cat-1.png"), [0]).unwrap();
+        let files = FileAccess::new(&[root.path().to_path_buf()]).await;
+        let canonical = files.roots()[0].clone();
+        let existing = root.path().join("cat.png");
+        let existing = existing.to_str().unwrap();
+
+        let fresh = root.path().join("dog.png");
+        assert_eq!(
+            files
+                .prepare_output(fresh.to_str().unwrap(), Some(OverwritePolicy::Fail))
+                .await
+                .unwrap()
+                .path(),
+            canonical.join("dog.png")
+        );
+        let renamed = files
+            .prepare_output(existing, Some(OverwritePolicy::Rename))
+            .await
+            .unwrap();
+        assert_eq!(renamed.path(), canonical.join("cat-2.png"));
+        // The name is held until the output is dropped without an image written
+        assert!(canonical.join("cat-2.png").exists());
+        drop(renamed);
+        assert!(!canonical.join("cat-2.png").exists());
+        assert!(
+            files
+                .prepare_output(existing, Some(OverwritePolicy::Fail))
+                .await
+                .is_err()
+        );
+        // Without a client that can ask, `ask` keeps the old overwrite behaviour
+        assert_eq!(
+            files.prepare_output(existing, None).await.unwrap().path(),
+            canonical.join("cat.png")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_renames_pick_different_names() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cat.png"), [0]).unwrap();
+        let files = FileAccess::new(&[root.path().to_path_buf()]).await;
+        let existing = root.path().join("cat.png");
+
+        let outputs = futures::future::join_all((0..5).map(|_| {
+            files.prepare_output(existing.to_str().unwrap(), Some(OverwritePolicy::Rename))
+        }))
+        .await;
+
+        let mut names: Vec<PathBuf> = outputs
+            .iter()
+            .map(|output| output.as_ref().unwrap().path().to_path_buf())
+            .collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 5);
+
+        // A written image stays when its output is dropped
+        std::fs::write(names[0].as_path(), [1, 2, 3]).unwrap();
+        drop(outputs);
+        assert_eq!(std::fs::read(&names[0]).unwrap(), [1, 2, 3]);
+        assert!(!names[1].exists());
+    }
+
+    #[tokio::test]
+    async fn test_prepare_output_asks_the_user() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cat.png"), [0]).unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let peer = Peer::new(tx);
+        let files = FileAccess::new(&[root.path().to_path_buf()])
+            .await
+            .with_overwrite(OverwritePolicy::Ask, Some(peer.clone()));
+        let existing = root.path().join("cat.png");
+
+        let client = tokio::spawn(async move {
+            for answer in [
+                json!({ "action": "accept", "content": { "action": "rename" } }),
+                json!({ "action": "decline" }),
+            ] {
+                let request: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+                assert_eq!(request["method"], "elicitation/create");
+                assert_eq!(
+                    request["params"]["requestedSchema"]["properties"]["action"]["enumNames"][1],
+                    "Save as cat-1.png"
+                );
+                peer.handle_response(json!({ "id": request["id"], "result": answer }));
+            }
+        });
+
+        let renamed = files
+            .prepare_output(existing.to_str().unwrap(), None)
+            .await
+            .unwrap();
+        assert!(renamed.path().ends_with("cat-1.png"));
+        // Giving up the reserved name lets the next prompt offer it again
+        drop(renamed);
+        let error = files
+            .prepare_output(existing.to_str().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, McpError::OverwriteDeclined(_)));
+        client.await.unwrap();
+    }
+
+    #[test]
+    fn test_roots_from_list_keeps_file_uris() {
+        let result = json!({
+            "roots": [
+                { "uri": "file:///home/user/project", "name": "project" },
+                { "uri": "https://example.com/repo" },
+                { "name": "no uri" }
+            ]
+        });
+
+        assert_eq!(
+            roots_from_list(&result),
+            vec![PathBuf::from("/home/user/project")]
+        );
+    }
+}
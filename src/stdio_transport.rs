@@ -0,0 +1,72 @@
+use crate::jsonrpc::{JsonRpcHandler, JsonRpcPayload};
+use crate::peer::Peer;
+use std::sync::Arc;
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::error;
+
+/// Serves newline-delimited JSON-RPC over stdin/stdout until stdin is closed.
+pub async fn serve(handler: JsonRpcHandler) {
+    // All outgoing messages go through a single writer task so concurrently
+    // completing requests and notifications never interleave on stdout
+    let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = io::stdout();
+        while let Some(message) = outgoing_rx.recv().await {
+            if let Err(e) = write_line(&mut stdout, &message).await {
+                error!("Failed to write response: {}", e);
+                break;
+            }
+        }
+    });
+
+    let handler = Arc::new(handler.with_peer(Peer::new(outgoing_tx.clone())));
+    let tool_changes = tokio::spawn({
+        let handler = Arc::clone(&handler);
+        async move { handler.forward_tool_list_changes().await }
+    });
+
+    let mut lines = BufReader::new(io::stdin()).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match JsonRpcPayload::parse(line.as_bytes()) {
+                    Ok(payload) => {
+                        let handler = Arc::clone(&handler);
+                        let outgoing_tx = outgoing_tx.clone();
+                        tokio::spawn(async move {
+                            if let Some(reply) = handler.handle_payload(payload).await {
+                                let _ = outgoing_tx.send(reply.to_json());
+                            }
+                        });
+                    }
+                    Err(error_response) => {
+                        let _ = outgoing_tx.send(error_response.to_json());
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(io_error) => {
+                error!("Failed to read from stdin: {}", io_error);
+                break;
+            }
+        }
+    }
+
+    // Let in-flight requests finish and flush their responses before exiting
+    tool_changes.abort();
+    let _ = tool_changes.await;
+    drop(handler);
+    drop(outgoing_tx);
+    let _ = writer.await;
+}
+
+async fn write_line(stdout: &mut io::Stdout, message: &str) -> io::Result<()> {
+    stdout.write_all(message.as_bytes()).await?;
+    stdout.write_all(b"\n").await?;
+    stdout.flush().await
+}
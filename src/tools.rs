@@ -0,0 +1,410 @@
+use crate::config::ServerConfig;
+use crate::error::McpError;
+use crate::gemini_client::GeneratedImage;
+use crate::jsonrpc::JsonRpcHandler;
+use crate::progress::ProgressReporter;
+use crate::roots::FileAccess;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tracing::error;
+
+/// Behaviour hints reported as MCP tool annotations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolAnnotations {
+    pub read_only: bool,
+    pub destructive: bool,
+    pub idempotent: bool,
+    pub open_world: bool,
+}
+
+/// Why a tool call failed.
+#[derive(Debug)]
+pub enum ToolError {
+    /// The call can't run as sent (malformed arguments, server not configured);
+    /// answered with a JSON-RPC error
+    InvalidRequest(McpError),
+    /// The tool ran and failed; answered with an `isError` result the model can act on
+    Failed(McpError),
+}
+
+impl From<McpError> for ToolError {
+    fn from(error: McpError) -> Self {
+        ToolError::Failed(error)
+    }
+}
+
+/// A tool served through `tools/list` and `tools/call`.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Human-readable name shown by clients
+    fn title(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// JSON Schema of the `arguments` object
+    fn input_schema(&self) -> Value;
+
+    /// JSON Schema of the `structuredContent` the tool returns, if any
+    fn output_schema(&self) -> Option<Value> {
+        None
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::default()
+    }
+
+    /// Runs the tool. `arguments` is `Value::Null` when the call sent none.
+    async fn call(&self, arguments: Value, context: &ToolContext<'_>) -> Result<Value, ToolError>;
+}
+
+/// Deserializes tool arguments, reporting missing or malformed ones as invalid requests.
+pub fn parse_arguments<T: DeserializeOwned>(tool: &str, arguments: Value) -> Result<T, ToolError> {
+    if arguments.is_null() {
+        return Err(ToolError::InvalidRequest(McpError::InvalidInput(
+            "Missing arguments".to_string(),
+        )));
+    }
+    serde_json::from_value(arguments).map_err(|e| {
+        error!("Invalid arguments for {}: {}", tool, e);
+        ToolError::InvalidRequest(McpError::InvalidInput(format!("Invalid arguments: {}", e)))
+    })
+}
+
+/// What a tool call can use from the server and the session it runs in.
+pub struct ToolContext<'a> {
+    handler: &'a JsonRpcHandler,
+    progress: ProgressReporter,
+}
+
+impl<'a> ToolContext<'a> {
+    pub(crate) fn new(handler: &'a JsonRpcHandler, progress: ProgressReporter) -> Self {
+        Self { handler, progress }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        self.handler.config()
+    }
+
+    /// Reports progress when the call sent `_meta.progressToken`.
+    pub fn progress(&self) -> &ProgressReporter {
+        &self.progress
+    }
+
+    /// The directories the call may read and write, with the session's overwrite handling.
+    pub async fn file_access(&self) -> FileAccess {
+        self.handler.file_access().await
+    }
+
+    /// Builds the result of a tool that saved an image: records it as a resource
+    /// and attaches it as an image block when the call or the server asks for it.
+    pub async fn generated_image_result(
+        &self,
+        text: String,
+        image: GeneratedImage,
+        include_image: Option<bool>,
+    ) -> Value {
+        self.handler
+            .generated_image_result(text, image, include_image)
+            .await
+    }
+}
+
+/// Which registered tools are offered to clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolSelection {
+    /// Only these tools are offered; `None` offers every tool
+    pub enable_tools: Option<Vec<String>>,
+    /// Tools never offered, even when enabled
+    pub disable_tools: Vec<String>,
+    /// Offer only tools annotated as read-only, e.g. no generators
+    pub read_only: bool,
+}
+
+impl ToolSelection {
+    pub fn allows(&self, tool: &dyn Tool) -> bool {
+        let name = tool.name().to_string();
+        (!self.read_only || tool.annotations().read_only)
+            && self
+                .enable_tools
+                .as_ref()
+                .is_none_or(|enabled| enabled.contains(&name))
+            && !self.disable_tools.contains(&name)
+    }
+
+    /// Names in the selection that no tool in `registry` has.
+    pub fn unknown_tools(&self, registry: &ToolRegistry) -> Vec<String> {
+        self.enable_tools
+            .iter()
+            .flatten()
+            .chain(&self.disable_tools)
+            .filter(|name| registry.get(name).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The tools a server offers, in the order `tools/list` reports them.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `tool`, replacing a registered tool with the same name.
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        match self.tools.iter_mut().find(|t| t.name() == tool.name()) {
+            Some(existing) => *existing = tool,
+            None => self.tools.push(tool),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.name() == name).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Whether `selection` offers a tool that isn't read-only, e.g. an image generator.
+    pub fn offers_writing_tools(&self, selection: &ToolSelection) -> bool {
+        self.tools
+            .iter()
+            .any(|tool| selection.allows(tool.as_ref()) && !tool.annotations().read_only)
+    }
+
+    /// The `tools` array of a `tools/list` result, limited to `selection`.
+    pub fn list(&self, selection: &ToolSelection) -> Vec<Value> {
+        self.tools
+            .iter()
+            .filter(|tool| selection.allows(tool.as_ref()))
+            .map(|tool| describe(tool.as_ref()))
+            .collect()
+    }
+}
+
+fn describe(tool: &dyn Tool) -> Value {
+    let annotations = tool.annotations();
+    let mut description = json!({
+        "name": tool.name(),
+        "title": tool.title(),
+        "description": tool.description(),
+        "inputSchema": tool.input_schema(),
+        "annotations": {
+            "title": tool.title(),
+            "readOnlyHint": annotations.read_only,
+            "destructiveHint": annotations.destructive,
+            "idempotentHint": annotations.idempotent,
+            "openWorldHint": annotations.open_world,
+        }
+    });
+    if let Some(output_schema) = tool.output_schema() {
+        description["outputSchema"] = output_schema;
+    }
+    description
+}
+
+/// Turns a failure while executing a tool into an `isError` tool result, so the
+/// model sees what went wrong and can correct its next call.
+pub(crate) fn tool_error_result(error: &McpError) -> Value {
+    let text = match error {
+        McpError::InvalidInput(msg) => {
+            format!(
+                "Invalid input: {}. Fix the arguments and call the tool again.",
+                msg
+            )
+        }
+        McpError::FileSystemError(msg) => format!(
+            "File system error: {}. Check that input files exist and that the output directory exists and is writable.",
+            msg
+        ),
+        McpError::ContentTypeError(msg) => format!(
+            "Unsupported content: {}. Use a jpg, jpeg, png, gif, webp, bmp or tiff image.",
+            msg
+        ),
+        McpError::RateLimitError(msg) => format!(
+            "Rate limit exceeded: {}. Wait a minute before retrying.",
+            msg
+        ),
+        McpError::Timeout(msg) => format!(
+            "Timed out: {}. Retry, or use smaller input images or a simpler prompt.",
+            msg
+        ),
+        McpError::NetworkError(e) => format!(
+            "Network error: {}. Check that image URLs are reachable and retry.",
+            e
+        ),
+        McpError::GeminiApiError { code, message } => format!(
+            "Gemini API error ({}): {}. If the request was refused, rephrase the prompt.",
+            code, message
+        ),
+        McpError::AuthenticationError(msg) => format!(
+            "Authentication error: {}. The server's Gemini API key must be fixed; retrying will not help.",
+            msg
+        ),
+        McpError::ConfigurationError(msg) => format!(
+            "Configuration error: {}. The server must be reconfigured; retrying will not help.",
+            msg
+        ),
+        McpError::OverwriteDeclined(_) => format!(
+            "{}; do not retry with the same output_path unless the user asks to.",
+            error
+        ),
+        _ => format!("Internal error: {}", error),
+    };
+
+    json!({
+        "content": [
+            {
+                "type": "text",
+                "text": text
+            }
+        ],
+        "isError": true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::JsonRpcRequest;
+
+    struct EchoTool;
+
+    #[async_trait]
+    impl Tool for EchoTool {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn title(&self) -> &str {
+            "Echo"
+        }
+
+        fn description(&self) -> &str {
+            "Returns its text argument"
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({ "type": "object", "properties": { "text": { "type": "string" } } })
+        }
+
+        fn annotations(&self) -> ToolAnnotations {
+            ToolAnnotations {
+                read_only: true,
+                ..ToolAnnotations::default()
+            }
+        }
+
+        async fn call(
+            &self,
+            arguments: Value,
+            _context: &ToolContext<'_>,
+        ) -> Result<Value, ToolError> {
+            match arguments["text"].as_str() {
+                Some("") => Err(McpError::InvalidInput("text is empty".to_string()).into()),
+                Some(text) => Ok(json!({ "content": [{ "type": "text", "text": text }] })),
+                None => Err(ToolError::InvalidRequest(McpError::InvalidInput(
+                    "text is required".to_string(),
+                ))),
+            }
+        }
+    }
+
+    fn call_echo(arguments: Value) -> JsonRpcRequest {
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": "echo", "arguments": arguments })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registered_tools_are_listed_and_called() {
+        let handler = JsonRpcHandler::new(None).with_tool(EchoTool);
+        let session = handler.new_session();
+
+        let result = session
+            .handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "tools/list".to_string(),
+                params: None,
+            })
+            .await
+            .result
+            .unwrap();
+        let tools = result["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 9);
+        let echo = tools.last().unwrap();
+        assert_eq!(echo["name"], "echo");
+        assert_eq!(echo["annotations"]["readOnlyHint"], true);
+        assert!(echo.get("outputSchema").is_none());
+
+        let response = session
+            .handle_request(call_echo(json!({ "text": "hi" })))
+            .await;
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "hi");
+
+        let response = session
+            .handle_request(call_echo(json!({ "text": "" })))
+            .await;
+        assert_eq!(response.result.unwrap()["isError"], true);
+
+        let response = session.handle_request(call_echo(json!({}))).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_tool_selection() {
+        let mut registry = ToolRegistry::new();
+        for tool in crate::gemini_tools::gemini_tools(None) {
+            registry.register(tool);
+        }
+        let names = |selection: &ToolSelection| -> Vec<String> {
+            registry
+                .list(selection)
+                .iter()
+                .map(|tool| tool["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(names(&ToolSelection::default()).len(), 8);
+        let read_only = ToolSelection {
+            read_only: true,
+            ..ToolSelection::default()
+        };
+        assert_eq!(names(&read_only), vec!["analyze_image", "list_models"]);
+        let selection = ToolSelection {
+            enable_tools: Some(vec!["analyze_image".into(), "edit_image".into()]),
+            disable_tools: vec!["edit_image".into(), "paint".into()],
+            read_only: false,
+        };
+        assert_eq!(names(&selection), vec!["analyze_image"]);
+        assert_eq!(selection.unknown_tools(&registry), vec!["paint"]);
+    }
+
+    #[test]
+    fn test_register_replaces_tools_with_the_same_name() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Arc::new(EchoTool));
+        registry.register(Arc::new(EchoTool));
+
+        assert_eq!(registry.len(), 1);
+        assert!(registry.get("echo").is_some());
+        assert!(registry.get("missing").is_none());
+    }
+}
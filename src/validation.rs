@@ -1,6 +1,8 @@
 use crate::error::{McpError, McpResult};
 use std::path::Path;
 
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif"];
+
 pub trait Validator<T> {
     fn validate(&self, input: &T) -> McpResult<()>;
 }
@@ -66,16 +68,10 @@ impl Validator<String> for OutputPathValidator {
             ));
         }
 
-        let allowed_extensions = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif"];
-        let path_lower = path.to_lowercase();
-        let has_valid_extension = allowed_extensions
-            .iter()
-            .any(|&ext| path_lower.ends_with(&format!(".{}", ext)));
-
-        if !has_valid_extension {
+        if !has_image_extension(path) {
             return Err(McpError::InvalidInput(format!(
                 "Unsupported output file extension. Allowed: {}",
-                allowed_extensions.join(", ")
+                IMAGE_EXTENSIONS.join(", ")
             )));
         }
 
@@ -120,22 +116,24 @@ fn validate_file_path(path: &str) -> McpResult<()> {
         ));
     }
 
-    let allowed_extensions = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif"];
-    let path_lower = path.to_lowercase();
-    let has_valid_extension = allowed_extensions
-        .iter()
-        .any(|&ext| path_lower.ends_with(&format!(".{}", ext)));
-
-    if !has_valid_extension {
+    if !has_image_extension(path) {
         return Err(McpError::InvalidInput(format!(
             "Unsupported file extension. Allowed: {}",
-            allowed_extensions.join(", ")
+            IMAGE_EXTENSIONS.join(", ")
         )));
     }
 
     Ok(())
 }
 
+/// Whether `path` ends in one of the supported image file extensions.
+pub fn has_image_extension(path: &str) -> bool {
+    let path_lower = path.to_lowercase();
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|&ext| path_lower.ends_with(&format!(".{}", ext)))
+}
+
 pub fn get_mime_type_from_extension(path: &str) -> String {
     let extension = Path::new(path)
         .extension()
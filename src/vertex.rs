@@ -0,0 +1,367 @@
+use crate::error::{McpError, McpResult};
+use crate::gemini_client::parse_endpoint_url;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{error, info};
+
+/// Token endpoint used when neither `--vertex-token-url` nor the key file names one
+pub const DEFAULT_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+/// Region used when `--vertex-location` isn't given
+pub const DEFAULT_LOCATION: &str = "us-central1";
+
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const JWT_BEARER_GRANT: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+/// Lifetime of a signed assertion; Google accepts at most an hour
+const ASSERTION_LIFETIME: Duration = Duration::from_secs(3600);
+/// Cached access tokens are replaced this long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// A service-account JSON key as downloaded from the Google Cloud console.
+#[derive(Clone)]
+pub struct ServiceAccountKey {
+    client_email: String,
+    private_key_id: Option<String>,
+    signing_key: EncodingKey,
+    token_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ServiceAccountFile {
+    client_email: String,
+    private_key: String,
+    private_key_id: Option<String>,
+    token_uri: Option<String>,
+}
+
+impl ServiceAccountKey {
+    pub fn load(path: &Path) -> McpResult<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            McpError::ConfigurationError(format!("Cannot read {}: {}", path.display(), e))
+        })?;
+        Self::from_json(&contents).map_err(|e| match e {
+            McpError::ConfigurationError(msg) => {
+                McpError::ConfigurationError(format!("{}: {}", path.display(), msg))
+            }
+            e => e,
+        })
+    }
+
+    pub fn from_json(json: &str) -> McpResult<Self> {
+        let file: ServiceAccountFile = serde_json::from_str(json).map_err(|e| {
+            McpError::ConfigurationError(format!("Invalid service account key: {}", e))
+        })?;
+        let signing_key = EncodingKey::from_rsa_pem(file.private_key.as_bytes()).map_err(|e| {
+            McpError::ConfigurationError(format!("Invalid service account private key: {}", e))
+        })?;
+
+        Ok(Self {
+            client_email: file.client_email,
+            private_key_id: file.private_key_id,
+            signing_key,
+            token_uri: file.token_uri,
+        })
+    }
+
+    pub fn client_email(&self) -> &str {
+        &self.client_email
+    }
+}
+
+impl fmt::Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where Vertex AI requests go and the service account that signs them.
+#[derive(Debug, Clone)]
+pub struct VertexConfig {
+    pub project: String,
+    pub location: String,
+    pub key: ServiceAccountKey,
+    /// OAuth endpoint the signed assertion is exchanged at
+    pub token_url: String,
+}
+
+impl VertexConfig {
+    /// `token_url` overrides the key's `token_uri`; both fall back to Google's endpoint.
+    pub fn new(
+        project: String,
+        location: String,
+        key: ServiceAccountKey,
+        token_url: Option<&str>,
+    ) -> McpResult<Self> {
+        let token_url = token_url
+            .or(key.token_uri.as_deref())
+            .unwrap_or(DEFAULT_TOKEN_URL);
+        let token_url = parse_endpoint_url("token URL", token_url)?;
+        Ok(Self {
+            project,
+            location,
+            key,
+            token_url,
+        })
+    }
+
+    /// The regional Vertex AI endpoint for `location`.
+    pub fn default_base_url(&self) -> String {
+        if self.location == "global" {
+            "https://aiplatform.googleapis.com/v1".to_string()
+        } else {
+            format!("https://{}-aiplatform.googleapis.com/v1", self.location)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        Instant::now() + TOKEN_REFRESH_MARGIN < self.expires_at
+    }
+}
+
+/// Signs service-account assertions, exchanges them for OAuth access tokens and
+/// caches each token until shortly before it expires.
+pub struct VertexAuth {
+    config: VertexConfig,
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl VertexAuth {
+    pub fn new(config: VertexConfig) -> Self {
+        Self {
+            config,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// The `generateContent` URL of a Google publisher model under `base_url`.
+    pub fn model_url(&self, base_url: &str, model: &str) -> String {
+        format!(
+            "{}/projects/{}/locations/{}/publishers/google/models/{}:generateContent",
+            base_url, self.config.project, self.config.location, model
+        )
+    }
+
+    /// A valid access token, fetched with `http` when none is cached or the cached one is about to expire.
+    pub async fn access_token(&self, http: &reqwest::Client) -> McpResult<String> {
+        // Held across the exchange so concurrent calls share one refresh
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| token.is_fresh()) {
+            return Ok(token.value.clone());
+        }
+
+        let fresh = self.exchange(http).await?;
+        let value = fresh.value.clone();
+        *token = Some(fresh);
+        Ok(value)
+    }
+
+    /// Drops the cached token, e.g. after Vertex AI rejected it.
+    pub async fn invalidate(&self) {
+        self.token.lock().await.take();
+    }
+
+    async fn exchange(&self, http: &reqwest::Client) -> McpResult<AccessToken> {
+        let response = http
+            .post(&self.config.token_url)
+            .form(&[
+                ("grant_type", JWT_BEARER_GRANT),
+                ("assertion", &self.assertion()?),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Token exchange returned {}: {}", status, error_text);
+            return Err(McpError::AuthenticationError(format!(
+                "Service account {} could not get an access token ({}): {}",
+                self.config.key.client_email, status, error_text
+            )));
+        }
+
+        let token: TokenResponse = response.json().await?;
+        info!(
+            "Obtained Vertex AI access token for {}, valid for {}s",
+            self.config.key.client_email, token.expires_in
+        );
+        Ok(AccessToken {
+            value: token.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        })
+    }
+
+    fn assertion(&self) -> McpResult<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let key = &self.config.key;
+        let claims = Claims {
+            iss: &key.client_email,
+            scope: SCOPE,
+            aud: &self.config.token_url,
+            iat: now,
+            exp: now + ASSERTION_LIFETIME.as_secs(),
+        };
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = key.private_key_id.clone();
+
+        jsonwebtoken::encode(&header, &claims, &key.signing_key).map_err(|e| {
+            McpError::AuthenticationError(format!("Cannot sign service account assertion: {}", e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{DecodingKey, Validation};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn test_config(token_url: &str) -> VertexConfig {
+        let key = ServiceAccountKey::load(Path::new("test/fake_service_account.json")).unwrap();
+        VertexConfig::new(
+            "test-project".to_string(),
+            DEFAULT_LOCATION.to_string(),
+            key,
+            Some(token_url),
+        )
+        .unwrap()
+    }
+
+    /// Serves `/token`, answering every exchange with a token that lives `expires_in` seconds.
+    async fn fake_token_server(expires_in: u64) -> (String, Arc<AtomicUsize>) {
+        let exchanges = Arc::new(AtomicUsize::new(0));
+        let counter = exchanges.clone();
+        let router = axum::Router::new().route(
+            "/token",
+            axum::routing::post(
+                move |axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    async move {
+                        assert_eq!(form["grant_type"], JWT_BEARER_GRANT);
+                        assert!(!form["assertion"].is_empty());
+                        axum::Json(json!({
+                            "access_token": format!("token-{}", n),
+                            "expires_in": expires_in,
+                            "token_type": "Bearer"
+                        }))
+                    }
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router).into_future());
+        (format!("http://{}/token", address), exchanges)
+    }
+
+    #[test]
+    fn test_assertion_claims() {
+        let auth = VertexAuth::new(test_config("http://localhost:9000/token"));
+
+        let assertion = auth.assertion().unwrap();
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.insecure_disable_signature_validation();
+        validation.set_audience(&["http://localhost:9000/token"]);
+        let decoded =
+            jsonwebtoken::decode::<Value>(&assertion, &DecodingKey::from_secret(&[]), &validation)
+                .unwrap();
+        assert_eq!(decoded.header.kid.as_deref(), Some("test-key-id"));
+        assert_eq!(
+            decoded.claims["iss"],
+            "mcp-test@test-project.iam.gserviceaccount.com"
+        );
+        assert_eq!(decoded.claims["scope"], SCOPE);
+        assert_eq!(
+            decoded.claims["exp"].as_u64().unwrap() - decoded.claims["iat"].as_u64().unwrap(),
+            3600
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_tokens_are_cached_until_they_near_expiry() {
+        let http = reqwest::Client::new();
+
+        let (token_url, exchanges) = fake_token_server(3600).await;
+        let auth = VertexAuth::new(test_config(&token_url));
+        assert_eq!(auth.access_token(&http).await.unwrap(), "token-1");
+        assert_eq!(auth.access_token(&http).await.unwrap(), "token-1");
+        assert_eq!(exchanges.load(Ordering::SeqCst), 1);
+        auth.invalidate().await;
+        assert_eq!(auth.access_token(&http).await.unwrap(), "token-2");
+
+        // Tokens inside the refresh margin are replaced on the next call
+        let (token_url, exchanges) = fake_token_server(30).await;
+        let auth = VertexAuth::new(test_config(&token_url));
+        auth.access_token(&http).await.unwrap();
+        assert_eq!(auth.access_token(&http).await.unwrap(), "token-2");
+        assert_eq!(exchanges.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_vertex_config() {
+        let config = test_config("http://127.0.0.1:9000/token");
+        assert_eq!(
+            config.default_base_url(),
+            "https://us-central1-aiplatform.googleapis.com/v1"
+        );
+        assert_eq!(
+            VertexAuth::new(config).model_url("http://127.0.0.1:9000/v1", "gemini-2.5-flash"),
+            "http://127.0.0.1:9000/v1/projects/test-project/locations/us-central1/publishers/google/models/gemini-2.5-flash:generateContent"
+        );
+
+        let key = ServiceAccountKey::load(Path::new("test/fake_service_account.json")).unwrap();
+        let config =
+            VertexConfig::new("p".to_string(), "global".to_string(), key.clone(), None).unwrap();
+        assert_eq!(config.token_url, DEFAULT_TOKEN_URL);
+        assert_eq!(
+            config.default_base_url(),
+            "https://aiplatform.googleapis.com/v1"
+        );
+        assert!(
+            VertexConfig::new(
+                "p".to_string(),
+                "global".to_string(),
+                key,
+                Some("http://tokens.example.com")
+            )
+            .is_err()
+        );
+        assert!(
+            ServiceAccountKey::from_json(r#"{"client_email": "a", "private_key": "b"}"#).is_err()
+        );
+    }
+}